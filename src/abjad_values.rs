@@ -0,0 +1,159 @@
+use abjad::{Abjad, AbjadPrefs, LetterOrder};
+use rusqlite::Connection;
+
+use crate::error::{Error, Location};
+use crate::metadata::{get_metadata, set_metadata};
+
+//
+// Constants
+//
+
+pub const ABJAD_PREFS_KEY: &str = "abjad_prefs";
+
+//
+// Public functions
+//
+
+#[must_use]
+pub fn abjad_value(headword_persian: &str, prefs: AbjadPrefs) -> u32 {
+    headword_persian.abjad(prefs)
+}
+
+// E.g., "count_shaddah=false, double_alif_maddah=false, ignore_lone_hamzah=false,
+// letter_order=mashriqi"
+#[must_use]
+pub fn prefs_to_string(prefs: AbjadPrefs) -> String {
+    let letter_order = match prefs.letter_order {
+        LetterOrder::Mashriqi => "mashriqi",
+        LetterOrder::Maghribi => "maghribi",
+    };
+
+    format!(
+        "count_shaddah={}, double_alif_maddah={}, ignore_lone_hamzah={}, letter_order={}",
+        prefs.count_shaddah, prefs.double_alif_maddah, prefs.ignore_lone_hamzah, letter_order
+    )
+}
+
+pub fn parse_prefs(input: &str) -> Result<AbjadPrefs, Error> {
+    let mut prefs = AbjadPrefs::default();
+
+    let invalid = |part: &str| Error::Parse {
+        location: Location::default(),
+        message: format!("Invalid abjad preference: {}", part),
+    };
+
+    for part in input.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = part.split_once('=').ok_or_else(|| invalid(part))?;
+
+        match (key.trim(), value.trim()) {
+            ("letter_order", "mashriqi") => prefs.letter_order = LetterOrder::Mashriqi,
+            ("letter_order", "maghribi") => prefs.letter_order = LetterOrder::Maghribi,
+            ("count_shaddah", value) => {
+                prefs.count_shaddah = value.parse().map_err(|_| invalid(part))?;
+            }
+            ("double_alif_maddah", value) => {
+                prefs.double_alif_maddah = value.parse().map_err(|_| invalid(part))?;
+            }
+            ("ignore_lone_hamzah", value) => {
+                prefs.ignore_lone_hamzah = value.parse().map_err(|_| invalid(part))?;
+            }
+            _ => return Err(invalid(part)),
+        }
+    }
+
+    Ok(prefs)
+}
+
+// The preferences the DB's abjad values were computed with; the crate defaults if none recorded
+pub fn load_abjad_prefs(conn: &Connection) -> Result<AbjadPrefs, Error> {
+    get_metadata(conn, ABJAD_PREFS_KEY)?
+        .map_or_else(|| Ok(AbjadPrefs::default()), |s| parse_prefs(&s))
+}
+
+pub fn record_abjad_prefs(conn: &Connection, prefs: AbjadPrefs) -> Result<(), Error> {
+    set_metadata(conn, ABJAD_PREFS_KEY, &prefs_to_string(prefs))
+}
+
+// Recomputes every abjad value with the given preferences, and records them
+pub fn recompute_abjad(conn: &Connection, prefs: AbjadPrefs) -> Result<usize, Error> {
+    let tx = conn.unchecked_transaction()?;
+    let count = fill_abjad(&tx, prefs)?;
+    tx.commit()?;
+
+    Ok(count)
+}
+
+//
+// Private functions
+//
+
+// Leaves the transaction to the caller
+fn fill_abjad(conn: &Connection, prefs: AbjadPrefs) -> Result<usize, Error> {
+    let rows: Vec<(i64, String)> = conn
+        .prepare("SELECT id, headword_persian FROM entries")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut stmt = conn.prepare("UPDATE entries SET abjad = ?1 WHERE id = ?2")?;
+
+    for (id, headword_persian) in &rows {
+        stmt.execute((abjad_value(headword_persian, prefs), id))?;
+    }
+
+    record_abjad_prefs(conn, prefs)?;
+
+    Ok(rows.len())
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ensure_table, insert_row, parse_entry};
+
+    #[test]
+    fn prefs_strings() {
+        let prefs = AbjadPrefs {
+            count_shaddah: true,
+            letter_order: LetterOrder::Maghribi,
+            ..AbjadPrefs::default()
+        };
+
+        assert_eq!(parse_prefs(&prefs_to_string(prefs)).unwrap(), prefs);
+        assert_eq!(parse_prefs("").unwrap(), AbjadPrefs::default());
+        assert!(parse_prefs("count_shaddah=maybe").is_err());
+        assert!(parse_prefs("hamzah=true").is_err());
+    }
+
+    #[test]
+    fn values() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_table(&conn).unwrap();
+        assert_eq!(load_abjad_prefs(&conn).unwrap(), AbjadPrefs::default());
+
+        let entry = parse_entry(
+            3,
+            1,
+            "<div><hw><pa>آب</pa> <i>āb</i></hw>, Water</div>".to_owned(),
+        )
+        .unwrap();
+        assert_eq!(entry.abjad, 3);
+
+        insert_row(&conn, entry).unwrap();
+
+        let prefs = AbjadPrefs {
+            double_alif_maddah: true,
+            ..AbjadPrefs::default()
+        };
+        assert_eq!(recompute_abjad(&conn, prefs).unwrap(), 1);
+        assert_eq!(load_abjad_prefs(&conn).unwrap(), prefs);
+
+        let abjad: u32 = conn
+            .query_row("SELECT abjad FROM entries", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(abjad, 4);
+    }
+}
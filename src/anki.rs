@@ -0,0 +1,501 @@
+use std::io::{self, Seek, Write};
+
+use rusqlite::{Connection, DatabaseName};
+use serde_json::{Value, json};
+use zip::write::{SimpleFileOptions, ZipWriter};
+use zip::{CompressionMethod, DateTime};
+
+use crate::error::Error;
+use crate::export::{Filter, for_each_filtered};
+use crate::langs::Lang;
+use crate::now;
+use crate::xml::{HTML_TAGS, escape_xml, markdown_to_text, markdown_to_xml};
+
+//
+// Constants
+//
+
+pub const DEFAULT_DECK_NAME: &str = "Steingass Persian-English";
+
+// Fixed IDs, so that importing a newer deck updates the notes of an older one (which Anki
+// matches by GUID, here the entry UID) rather than adding a second note type
+const MODEL_ID: i64 = 1_712_345_678_901;
+const DECK_ID_BASE: i64 = 1_712_000_000_000;
+const NOTE_ID_BASE: i64 = 1_713_000_000_000;
+const CARD_ID_BASE: i64 = 1_714_000_000_000;
+
+// Anki separates the fields of a note with the unit separator
+const FIELD_SEPARATOR: &str = "\x1f";
+
+// The schema of an Anki 2.1 collection (version 11), which every version of Anki can import
+const COLLECTION_SCHEMA: &str = "
+    CREATE TABLE col (
+        id integer PRIMARY KEY,
+        crt integer NOT NULL,
+        mod integer NOT NULL,
+        scm integer NOT NULL,
+        ver integer NOT NULL,
+        dty integer NOT NULL,
+        usn integer NOT NULL,
+        ls integer NOT NULL,
+        conf text NOT NULL,
+        models text NOT NULL,
+        decks text NOT NULL,
+        dconf text NOT NULL,
+        tags text NOT NULL
+    );
+    CREATE TABLE notes (
+        id integer PRIMARY KEY,
+        guid text NOT NULL,
+        mid integer NOT NULL,
+        mod integer NOT NULL,
+        usn integer NOT NULL,
+        tags text NOT NULL,
+        flds text NOT NULL,
+        sfld integer NOT NULL,
+        csum integer NOT NULL,
+        flags integer NOT NULL,
+        data text NOT NULL
+    );
+    CREATE TABLE cards (
+        id integer PRIMARY KEY,
+        nid integer NOT NULL,
+        did integer NOT NULL,
+        ord integer NOT NULL,
+        mod integer NOT NULL,
+        usn integer NOT NULL,
+        type integer NOT NULL,
+        queue integer NOT NULL,
+        due integer NOT NULL,
+        ivl integer NOT NULL,
+        factor integer NOT NULL,
+        reps integer NOT NULL,
+        lapses integer NOT NULL,
+        left integer NOT NULL,
+        odue integer NOT NULL,
+        odid integer NOT NULL,
+        flags integer NOT NULL,
+        data text NOT NULL
+    );
+    CREATE TABLE revlog (
+        id integer PRIMARY KEY,
+        cid integer NOT NULL,
+        usn integer NOT NULL,
+        ease integer NOT NULL,
+        ivl integer NOT NULL,
+        lastIvl integer NOT NULL,
+        factor integer NOT NULL,
+        time integer NOT NULL,
+        type integer NOT NULL
+    );
+    CREATE TABLE graves (
+        usn integer NOT NULL,
+        oid integer NOT NULL,
+        type integer NOT NULL
+    );
+    CREATE INDEX ix_notes_usn ON notes (usn);
+    CREATE INDEX ix_cards_usn ON cards (usn);
+    CREATE INDEX ix_revlog_usn ON revlog (usn);
+    CREATE INDEX ix_cards_nid ON cards (nid);
+    CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+    CREATE INDEX ix_revlog_cid ON revlog (cid);
+    CREATE INDEX ix_notes_csum ON notes (csum);
+";
+
+const CARD_CSS: &str = ".card { font-family: serif; font-size: 20px; text-align: center; }\n\
+    .persian { font-size: 32px; }\n\
+    .latin { font-style: italic; }";
+
+//
+// Public functions
+//
+
+// An in-memory Anki collection with one note per matching entry, and two cards per note:
+// Persian → English, and (for entries with definitions) English → Persian
+pub fn build_collection(
+    conn: &Connection,
+    deck_name: &str,
+    filter: &Filter,
+) -> Result<(Connection, usize), Error> {
+    let collection = Connection::open_in_memory()?;
+    collection.execute_batch(COLLECTION_SCHEMA)?;
+
+    let modified = i64::try_from(now()).unwrap_or_default();
+    let deck_id = DECK_ID_BASE + checksum(deck_name);
+    let mut count = 0;
+
+    {
+        let mut insert_note = collection.prepare(
+            "INSERT INTO notes (id, guid, mid, mod, usn, tags, flds, sfld, csum, flags, data)
+            VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+        )?;
+        let mut insert_card = collection.prepare(
+            "INSERT INTO cards (id, nid, did, ord, mod, usn, type, queue, due, ivl, factor, reps,
+                lapses, left, odue, odid, flags, data)
+            VALUES (?1, ?2, ?3, ?4, ?5, -1, 0, 0, ?6, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+        )?;
+
+        for_each_filtered(conn, filter, |id, entry| {
+            let persian = escape_xml(entry.headword_persian.trim());
+            let latin = markdown_to_xml(&entry.headword_latin, &HTML_TAGS);
+            let definitions = markdown_to_xml(&entry.definitions, &HTML_TAGS);
+            let fields = [persian.as_str(), latin.as_str(), definitions.as_str()];
+
+            // Tags are separated by spaces, so "Arabic & Persian" becomes "Arabic Persian"
+            let tags = if entry.lang == Lang::Unmarked {
+                String::new()
+            } else {
+                format!(" {} ", entry.lang.as_str().replace(" & ", " "))
+            };
+
+            // The sort field is stored as text without markup, and checksummed for duplicates
+            let sort_field = markdown_to_text(&entry.headword_persian).trim().to_owned();
+            let note_id = NOTE_ID_BASE + id;
+
+            insert_note.execute((
+                note_id,
+                &entry.uid,
+                MODEL_ID,
+                modified,
+                tags,
+                fields.join(FIELD_SEPARATOR),
+                &sort_field,
+                checksum(&sort_field),
+            ))?;
+
+            count += 1;
+
+            for ord in 0..2 {
+                // Matches the model's requirements: no card without its front
+                if ord == 1 && definitions.is_empty() {
+                    continue;
+                }
+
+                insert_card.execute((
+                    CARD_ID_BASE + id * 2 + ord,
+                    note_id,
+                    deck_id,
+                    ord,
+                    modified,
+                    count,
+                ))?;
+            }
+
+            Ok(())
+        })?;
+    }
+
+    collection.execute(
+        "INSERT INTO col (id, crt, mod, scm, ver, dty, usn, ls, conf, models, decks, dconf, tags)
+        VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        (
+            modified - modified % 86_400,
+            modified * 1000,
+            collection_conf(deck_id, count).to_string(),
+            models(deck_id, modified).to_string(),
+            decks(deck_id, deck_name, modified).to_string(),
+            deck_conf().to_string(),
+        ),
+    )?;
+
+    Ok((collection, count))
+}
+
+// An `.apkg` package: the collection, and an empty media list
+pub fn write_apkg<W: Write + Seek>(
+    conn: &Connection,
+    out: W,
+    deck_name: &str,
+    filter: &Filter,
+) -> Result<usize, Error> {
+    let (collection, count) = build_collection(conn, deck_name, filter)?;
+    let data = collection.serialize(DatabaseName::Main)?;
+
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(DateTime::default())
+        .unix_permissions(0o644);
+
+    let mut zip = ZipWriter::new(out);
+
+    zip.start_file("collection.anki2", options)
+        .map_err(io::Error::other)?;
+    zip.write_all(&data)?;
+
+    zip.start_file("media", options).map_err(io::Error::other)?;
+    zip.write_all(b"{}")?;
+
+    zip.finish().map_err(io::Error::other)?;
+
+    Ok(count)
+}
+
+//
+// Private functions
+//
+
+// As Anki's field checksum: the first 32 bits of the SHA-1 digest
+fn checksum(text: &str) -> i64 {
+    let digest = sha1_smol::Sha1::from(text).digest().bytes();
+    i64::from(u32::from_be_bytes([
+        digest[0], digest[1], digest[2], digest[3],
+    ]))
+}
+
+fn collection_conf(deck_id: i64, count: usize) -> Value {
+    json!({
+        "activeDecks": [deck_id],
+        "curDeck": deck_id,
+        "curModel": MODEL_ID,
+        "nextPos": count + 1,
+        "estTimes": true,
+        "dueCounts": true,
+        "newSpread": 0,
+        "newBury": true,
+        "collapseTime": 1200,
+        "timeLim": 0,
+        "sortType": "noteFld",
+        "sortBackwards": false,
+        "addToCur": true,
+    })
+}
+
+fn models(deck_id: i64, modified: i64) -> Value {
+    let field = |name: &str, ord: u8, rtl: bool| {
+        json!({
+            "name": name,
+            "ord": ord,
+            "sticky": false,
+            "rtl": rtl,
+            "font": "Arial",
+            "size": 20,
+            "media": [],
+        })
+    };
+
+    let template = |name: &str, ord: u8, qfmt: &str, afmt: &str| {
+        json!({
+            "name": name,
+            "ord": ord,
+            "qfmt": qfmt,
+            "afmt": afmt,
+            "did": null,
+            "bqfmt": "",
+            "bafmt": "",
+        })
+    };
+
+    json!({
+        MODEL_ID.to_string(): {
+            "id": MODEL_ID,
+            "name": "Steingass",
+            "type": 0,
+            "mod": modified,
+            "usn": -1,
+            "sortf": 0,
+            "did": deck_id,
+            "flds": [
+                field("Persian", 0, true),
+                field("Transliteration", 1, false),
+                field("Definitions", 2, false),
+            ],
+            "tmpls": [
+                template(
+                    "Persian → English",
+                    0,
+                    "<div class=persian dir=rtl>{{Persian}}</div>",
+                    "{{FrontSide}}\n\n<hr id=answer>\n\n\
+                     <div class=latin>{{Transliteration}}</div>\n<div>{{Definitions}}</div>",
+                ),
+                template(
+                    "English → Persian",
+                    1,
+                    "<div>{{Definitions}}</div>",
+                    "{{FrontSide}}\n\n<hr id=answer>\n\n\
+                     <div class=persian dir=rtl>{{Persian}}</div>\n\
+                     <div class=latin>{{Transliteration}}</div>",
+                ),
+            ],
+            "css": CARD_CSS,
+            "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\
+                \\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\
+                \\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "tags": [],
+            "vers": [],
+            // Card 1 needs the Persian field; card 2 needs the definitions
+            "req": [[0, "all", [0]], [1, "all", [2]]],
+        }
+    })
+}
+
+fn decks(deck_id: i64, deck_name: &str, modified: i64) -> Value {
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id,
+            "name": name,
+            "desc": "",
+            "mod": modified,
+            "usn": -1,
+            "dyn": 0,
+            "conf": 1,
+            "collapsed": false,
+            "browserCollapsed": false,
+            "extendNew": 10,
+            "extendRev": 50,
+            "newToday": [0, 0],
+            "revToday": [0, 0],
+            "lrnToday": [0, 0],
+            "timeToday": [0, 0],
+        })
+    };
+
+    // Anki expects the default deck to exist in every collection
+    json!({
+        "1": deck(1, "Default"),
+        deck_id.to_string(): deck(deck_id, deck_name),
+    })
+}
+
+fn deck_conf() -> Value {
+    json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": 0,
+            "usn": 0,
+            "dyn": false,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "new": {
+                "delays": [1, 10],
+                "ints": [1, 4, 7],
+                "initialFactor": 2500,
+                "order": 1,
+                "perDay": 20,
+                "bury": true,
+                "separate": true,
+            },
+            "rev": {
+                "perDay": 200,
+                "ease4": 1.3,
+                "fuzz": 0.05,
+                "ivlFct": 1,
+                "maxIvl": 36500,
+                "minSpace": 1,
+                "bury": true,
+            },
+            "lapse": {
+                "delays": [10],
+                "mult": 0,
+                "minInt": 1,
+                "leechFails": 8,
+                "leechAction": 0,
+            },
+        }
+    })
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::test_db;
+
+    fn setup() -> Connection {
+        test_db(&[
+            (
+                3,
+                "<div><lang>a</lang> <hw><pa>آب</pa> <i>āb</i></hw>, s. Water; <i>lustre</i></div>",
+            ),
+            (3, "<div><hw><pa>آباد</pa> <i>ābād</i></hw></div>"),
+        ])
+    }
+
+    #[test]
+    fn collection() {
+        let (collection, count) =
+            build_collection(&setup(), DEFAULT_DECK_NAME, &Filter::default()).unwrap();
+        assert_eq!(count, 2);
+
+        let (guid, flds, sfld, csum, tags): (String, String, String, i64, String) = collection
+            .query_row(
+                "SELECT guid, flds, sfld, csum, tags FROM notes ORDER BY id LIMIT 1",
+                [],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert!(guid.starts_with("0003-001-"));
+        assert_eq!(flds, "آب\x1f<i>āb</i>\x1fs. Water; <i>lustre</i>");
+        assert_eq!(sfld, "آب");
+        assert_eq!(csum, checksum("آب"));
+        assert_eq!(tags, " Arabic Persian ");
+
+        // No English → Persian card for the entry without definitions
+        let ords: Vec<i64> = collection
+            .prepare("SELECT ord FROM cards ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(ords, vec![0, 1, 0]);
+
+        let (models, decks): (String, String) = collection
+            .query_row("SELECT models, decks FROM col", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        let models: Value = serde_json::from_str(&models).unwrap();
+        let templates = &models[MODEL_ID.to_string()]["tmpls"];
+        assert_eq!(templates[1]["name"], "English → Persian");
+
+        let decks: Value = serde_json::from_str(&decks).unwrap();
+        let deck_id = DECK_ID_BASE + checksum(DEFAULT_DECK_NAME);
+        assert_eq!(decks[deck_id.to_string()]["name"], DEFAULT_DECK_NAME);
+
+        // "da39a3ee" is the start of the digest of the empty string
+        assert_eq!(checksum(""), 0xda39_a3ee);
+    }
+
+    #[test]
+    fn package() {
+        let mut out = Cursor::new(Vec::new());
+        let count = write_apkg(&setup(), &mut out, "Test", &Filter::default()).unwrap();
+        assert_eq!(count, 2);
+
+        let mut archive = ZipArchive::new(Cursor::new(out.into_inner())).unwrap();
+        let mut data = Vec::new();
+        archive
+            .by_name("collection.anki2")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert!(data.starts_with(b"SQLite format 3\0"));
+
+        let mut media = String::new();
+        archive
+            .by_name("media")
+            .unwrap()
+            .read_to_string(&mut media)
+            .unwrap();
+        assert_eq!(media, "{}");
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::Entry;
+use crate::langs::Lang;
+use crate::xml::{HTML_TAGS, escape_xml, markdown_to_text, markdown_to_xml};
+
+//
+// Type definitions
+//
+
+// How an entry is written out for a dictionary reader
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Default)]
+pub enum ArticleFormat {
+    #[default]
+    Html,
+    Text,
+}
+
+//
+// Constants
+//
+
+pub const DICTIONARY_TITLE: &str = "Steingass Persian-English Dictionary";
+pub const DICTIONARY_DESCRIPTION: &str = "F. J. Steingass, A Comprehensive Persian-English \
+    Dictionary (London, 1892), from the edition of the Digital South Asia Library";
+pub const DICTIONARY_AUTHOR: &str = "Francis Joseph Steingass";
+pub const DICTIONARY_URL: &str = "https://dsal.uchicago.edu/dictionaries/steingass/";
+
+//
+// Public functions
+//
+
+// The full headword, the language (unless unmarked), then the definitions
+#[must_use]
+pub fn render_article(entry: &Entry, format: ArticleFormat) -> String {
+    let lang = (entry.lang != Lang::Unmarked).then(|| entry.lang.as_str());
+
+    match format {
+        ArticleFormat::Html => {
+            let mut html = format!(
+                "<b>{}</b>",
+                markdown_to_xml(&entry.headword_full, &HTML_TAGS)
+            );
+
+            if let Some(lang) = lang {
+                write!(html, " <small>[{}]</small>", escape_xml(lang)).unwrap();
+            }
+
+            if !entry.definitions.is_empty() {
+                write!(
+                    html,
+                    "<br/>{}",
+                    markdown_to_xml(&entry.definitions, &HTML_TAGS)
+                )
+                .unwrap();
+            }
+
+            html
+        }
+        ArticleFormat::Text => {
+            let mut text = markdown_to_text(&entry.headword_full);
+
+            if let Some(lang) = lang {
+                write!(text, " [{}]", lang).unwrap();
+            }
+
+            if !entry.definitions.is_empty() {
+                text.push('\n');
+                text.push_str(&markdown_to_text(&entry.definitions));
+            }
+
+            text
+        }
+    }
+}
+
+// The words under which an entry is found: its Persian headword and its transliteration
+#[must_use]
+pub fn index_words(entry: &Entry) -> Vec<String> {
+    let mut words = Vec::new();
+
+    for word in [
+        entry.headword_persian.trim().to_owned(),
+        markdown_to_text(&entry.headword_latin).trim().to_owned(),
+    ] {
+        if !word.is_empty() && !words.contains(&word) {
+            words.push(word);
+        }
+    }
+
+    words
+}
+
+// A multi-file format's path for one of its files, e.g., "out/steingass" and ".dict.dz"
+#[must_use]
+pub fn dictionary_path(base: &Path, extension: &str) -> PathBuf {
+    let mut path = base.as_os_str().to_owned();
+    path.push(extension);
+    PathBuf::from(path)
+}
@@ -0,0 +1,344 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use abjad::AbjadPrefs;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Location};
+use crate::langs::Lang;
+use crate::{Entry, parse_entry_with};
+
+//
+// Type definitions
+//
+
+// Hand-made fixes to entries, kept outside the DB so that they survive re-scraping
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct Corrections {
+    pub version: u32,
+    #[serde(default, rename = "correction", skip_serializing_if = "Vec::is_empty")]
+    pub corrections: Vec<Correction>,
+}
+
+// Applies to the entry at `position` (1-based) among the results on `page`
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Serialize, Deserialize)]
+pub struct Correction {
+    pub page: u16,
+    pub position: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    // A snippet that the scraped HTML must contain, to guard against the page shifting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_html: Option<String>,
+    #[serde(default, skip_serializing_if = "FieldOverrides::is_empty")]
+    pub fields: FieldOverrides,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Serialize, Deserialize)]
+pub struct FieldOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headword_full: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headword_persian: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headword_latin: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub definitions: Option<String>,
+}
+
+//
+// Constants
+//
+
+pub const CORRECTIONS_VERSION: u32 = 1;
+
+//
+// Implementations
+//
+
+impl Default for Corrections {
+    fn default() -> Self {
+        Self {
+            version: CORRECTIONS_VERSION,
+            corrections: Vec::new(),
+        }
+    }
+}
+
+impl Corrections {
+    // A missing file is the same as an empty one
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = fs::read_to_string(path)?;
+        Self::from_str(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let text = toml::to_string_pretty(self).map_err(|e| Error::Parse {
+            location: Location::default(),
+            message: e.to_string(),
+        })?;
+
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn find(&self, page: u16, position: usize) -> Option<&Correction> {
+        self.corrections
+            .iter()
+            .find(|c| c.page == page && c.position == position)
+    }
+
+    pub fn for_page(&self, page: u16) -> impl Iterator<Item = &Correction> {
+        self.corrections.iter().filter(move |c| c.page == page)
+    }
+
+    // Substitutes corrected HTML into the results from a page
+    pub fn apply_html(&self, page: u16, results: &mut [String]) -> Result<usize, Error> {
+        let mut applied = 0;
+
+        for correction in self.for_page(page) {
+            let Some(html) = correction
+                .position
+                .checked_sub(1)
+                .and_then(|i| results.get_mut(i))
+            else {
+                return Err(correction.error(format!("Page has only {} entries", results.len())));
+            };
+
+            if correction.apply_html(html)? {
+                applied += 1;
+            }
+        }
+
+        Ok(applied)
+    }
+
+    // Like `parse_entry_with`, with any correction for the entry applied
+    pub fn parse_entry(
+        &self,
+        page: u16,
+        position: usize,
+        raw_html: String,
+        prefs: AbjadPrefs,
+    ) -> Result<Entry, Error> {
+        let Some(correction) = self.find(page, position) else {
+            return parse_entry_with(page, position, raw_html, prefs);
+        };
+
+        let mut html = raw_html;
+        correction.apply_html(&mut html)?;
+
+        let mut entry = parse_entry_with(page, position, html, prefs)?;
+        let uid = entry.uid.clone();
+        correction.apply_fields(&mut entry)?;
+        entry.derive_fields(prefs);
+
+        // A permalink should not move when a headword is corrected
+        entry.uid = uid;
+
+        Ok(entry)
+    }
+}
+
+impl FromStr for Corrections {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed: Self = toml::from_str(s).map_err(|e| Error::Parse {
+            location: Location::default(),
+            message: format!("Invalid corrections file: {}", e),
+        })?;
+
+        if parsed.version != CORRECTIONS_VERSION {
+            return Err(Error::Parse {
+                location: Location::default(),
+                message: format!(
+                    "Corrections file is version {}; expected {}",
+                    parsed.version, CORRECTIONS_VERSION
+                ),
+            });
+        }
+
+        Ok(parsed)
+    }
+}
+
+impl Correction {
+    // Returns whether anything was changed; HTML that is already corrected is left alone
+    pub fn apply_html(&self, html: &mut String) -> Result<bool, Error> {
+        let Some(replacement) = &self.raw_html else {
+            return Ok(false);
+        };
+
+        if html == replacement {
+            return Ok(false);
+        }
+
+        if let Some(expect) = &self.expect
+            && !html.contains(expect.as_str())
+        {
+            return Err(self.error(format!("Stale correction; expected {:?}", expect)));
+        }
+
+        html.clone_from(replacement);
+        Ok(true)
+    }
+
+    // Leaves the derived columns (abjad value, etc.) to `Entry::derive_fields`
+    pub fn apply_fields(&self, entry: &mut Entry) -> Result<(), Error> {
+        let fields = &self.fields;
+
+        if let Some(lang) = &fields.lang {
+            entry.lang = Lang::from_str(lang).map_err(|e| self.error(e.to_string()))?;
+        }
+
+        for (value, field) in [
+            (&fields.headword_full, &mut entry.headword_full),
+            (&fields.headword_persian, &mut entry.headword_persian),
+            (&fields.headword_latin, &mut entry.headword_latin),
+            (&fields.definitions, &mut entry.definitions),
+        ] {
+            if let Some(value) = value {
+                field.clone_from(value);
+            }
+        }
+
+        Ok(())
+    }
+
+    const fn error(&self, message: String) -> Error {
+        Error::Parse {
+            location: Location::entry(self.page, self.position),
+            message,
+        }
+    }
+}
+
+impl FieldOverrides {
+    // The overrides that turn an entry as parsed into the entry as stored
+    #[must_use]
+    pub fn between(parsed: &Entry, stored: &Entry) -> Self {
+        let changed = |parsed: &String, stored: &String| (parsed != stored).then(|| stored.clone());
+
+        Self {
+            lang: (parsed.lang != stored.lang).then(|| stored.lang.as_str().to_owned()),
+            headword_full: changed(&parsed.headword_full, &stored.headword_full),
+            headword_persian: changed(&parsed.headword_persian, &stored.headword_persian),
+            headword_latin: changed(&parsed.headword_latin, &stored.headword_latin),
+            definitions: changed(&parsed.definitions, &stored.definitions),
+        }
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.lang.is_none()
+            && self.headword_full.is_none()
+            && self.headword_persian.is_none()
+            && self.headword_latin.is_none()
+            && self.definitions.is_none()
+    }
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::entry_uid;
+    use crate::parse_entry;
+
+    const SAMPLE: &str = r#"
+version = 1
+
+[[correction]]
+page = 14
+position = 2
+note = "Missing space"
+expect = "Suffused"
+raw_html = "<div><hw><pa>آتش‌آلود</pa> <i>ātish-ālūd</i></hw>, Suffused with fire.</div>"
+
+[[correction]]
+page = 5
+position = 1
+fields = { definitions = "The first of eight words (image removed)" }
+
+[[correction]]
+page = 1020
+position = 3
+fields = { headword_full = "نجز *najz*, *najaz*" }
+"#;
+
+    #[test]
+    fn parse_and_apply() {
+        let corrections = Corrections::from_str(SAMPLE).unwrap();
+        assert_eq!(corrections.corrections.len(), 3);
+
+        let mut results = vec![
+            "<div>first</div>".to_owned(),
+            "<div><hw><pa>آتش‌آلود</pa> <i>ātish-ālūd</i></hw>,Suffused with fire.</div>".to_owned(),
+        ];
+        assert_eq!(corrections.apply_html(14, &mut results).unwrap(), 1);
+        assert!(results[1].contains(", Suffused"));
+
+        // Already corrected
+        assert_eq!(corrections.apply_html(14, &mut results).unwrap(), 0);
+
+        let entry = corrections
+            .parse_entry(
+                5,
+                1,
+                "<div><hw><pa>ابجد</pa> <i>abjad</i></hw>, x</div>".to_owned(),
+                AbjadPrefs::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            entry.definitions,
+            "The first of eight words (image removed)"
+        );
+        assert_eq!(entry.headword_latin, "*abjad*");
+
+        // The headword is corrected, but the uid is that of the entry as scraped
+        let html = "<div><hw><pa>نجز</pa> <i>najz</i></hw>, Fulfilling</div>";
+        let entry = corrections
+            .parse_entry(1020, 3, html.to_owned(), AbjadPrefs::default())
+            .unwrap();
+        assert_eq!(entry.headword_full, "نجز *najz*, *najaz*");
+        assert_eq!(
+            entry.uid,
+            parse_entry(1020, 3, html.to_owned()).unwrap().uid
+        );
+        assert_ne!(entry.uid, entry_uid(1020, 3, "نجز *najz*, *najaz*"));
+    }
+
+    #[test]
+    fn stale() {
+        let corrections = Corrections::from_str(SAMPLE).unwrap();
+
+        let mut results = vec!["<div>a</div>".to_owned(), "<div>b</div>".to_owned()];
+        assert!(corrections.apply_html(14, &mut results).is_err());
+
+        let mut results = vec!["<div>a</div>".to_owned()];
+        assert!(corrections.apply_html(14, &mut results).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let corrections = Corrections::from_str(SAMPLE).unwrap();
+        let serialized = toml::to_string_pretty(&corrections).unwrap();
+        assert_eq!(Corrections::from_str(&serialized).unwrap(), corrections);
+
+        assert!(Corrections::from_str("version = 99").is_err());
+    }
+}
@@ -1,16 +1,19 @@
-use crate::{clean_simple, pandoc};
+use crate::error::Error;
+use crate::{clean_simple, to_markdown};
 use regex::Regex;
 
-pub fn except_headword(input: &str) -> Result<String, anyhow::Error> {
+pub fn except_headword(input: &str) -> Result<String, Error> {
     let re_c = Regex::new("<c>.*</c>").unwrap();
     let re_hw = Regex::new("<hw>.*</hw>").unwrap();
     let re_lang = Regex::new("<lang>.*</lang>").unwrap();
+    let re_img = Regex::new("<img[^>]*>").unwrap(); // Kept separately as attachments
 
     let without_c = re_c.replace_all(input, "");
     let without_hw = re_hw.replace_all(&without_c, "");
     let without_lang = re_lang.replace_all(&without_hw, "");
+    let without_img = re_img.replace_all(&without_lang, "");
 
-    let processed = pandoc(&without_lang)?;
+    let processed = to_markdown(&without_img);
     let cleaned = clean_defs(&processed);
 
     Ok(cleaned)
@@ -53,7 +56,9 @@ fn clean_defs(input: &str) -> String {
 mod tests {
     use super::*;
     use crate::charsets::{ARABIC_ALLOWED, DEFS_GREEK, DEFS_HEBREW, DEFS_MISC, OTHER_ALLOWED};
+    use crate::corrections::Corrections;
     use rusqlite::Connection;
+    use std::path::Path;
 
     #[test]
     fn chars() {
@@ -123,38 +128,45 @@ mod tests {
         }
     }
 
-    // This will take a while to run; it has not yet run completely
-    // #[test]
-    fn _values_slow() {
+    // Regenerates every value from the raw HTML
+    #[test]
+    fn values_regen() {
+        let corrections = Corrections::load(Path::new("corrections.toml")).unwrap();
+
         let conn = Connection::open("entries.sqlite").unwrap();
         let mut stmt = conn
-            .prepare("SELECT id, raw_html, definitions FROM entries")
+            .prepare("SELECT id, page, position, raw_html, definitions FROM entries")
             .unwrap();
 
         let entry_iter = stmt
             .query_map([], |row| {
                 let id: u32 = row.get(0).unwrap();
-                let raw_html: String = row.get(1).unwrap();
-                let definitions: String = row.get(2).unwrap();
-                Ok((id, raw_html, definitions))
+                let page: u16 = row.get(1).unwrap();
+                let position: usize = row.get(2).unwrap();
+                let raw_html: String = row.get(3).unwrap();
+                let definitions: String = row.get(4).unwrap();
+                Ok((id, page, position, raw_html, definitions))
             })
             .unwrap();
 
         for entry in entry_iter {
-            let (id, raw_html, definitions) = entry.unwrap();
-
-            // Skip entry for abjad
-            if definitions.contains("image removed") {
-                continue;
-            }
+            let (id, page, position, raw_html, definitions) = entry.unwrap();
 
             if id % 100 == 0 {
                 println!("Checking ID {}", id);
             }
 
-            let definitions_regen = except_headword(&raw_html).unwrap();
-
-            assert_eq!(definitions_regen, definitions);
+            // Definitions fixed by hand (e.g., for abjad) come from the corrections file
+            let definitions_regen = corrections
+                .find(page, position)
+                .and_then(|c| c.fields.definitions.clone())
+                .unwrap_or_else(|| except_headword(&raw_html).unwrap());
+
+            assert_eq!(
+                definitions_regen, definitions,
+                "p. {}, entry {} was fixed by hand; record the fix with `corrections --extract`",
+                page, position
+            );
         }
     }
 }
@@ -0,0 +1,211 @@
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::articles::{
+    ArticleFormat, DICTIONARY_DESCRIPTION, DICTIONARY_TITLE, DICTIONARY_URL, dictionary_path,
+    index_words, render_article,
+};
+use crate::dictzip::write_dictzip;
+use crate::error::Error;
+use crate::for_each_entry;
+
+//
+// Type definitions
+//
+
+// The two files of a dictd database, before the article data is compressed
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+pub struct Dictd {
+    pub index: String,
+    pub dict: Vec<u8>,
+    pub entries: usize,
+}
+
+//
+// Constants
+//
+
+// Offsets and lengths in the index are written in base 64 with this alphabet
+const B64_DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+//
+// Public functions
+//
+
+// One article per entry, found under both its Persian and its Latin headword, along with the
+// "00-database-*" entries that describe the database to `dictd`
+pub fn build_dictd(conn: &Connection, format: ArticleFormat) -> Result<Dictd, Error> {
+    let mut dict = Vec::new();
+    let mut words: Vec<(String, usize, usize)> = Vec::new();
+
+    let mut headers = vec![
+        ("00-database-short", format!("     {}\n", DICTIONARY_TITLE)),
+        (
+            "00-database-info",
+            format!("     {}\n", DICTIONARY_DESCRIPTION),
+        ),
+        ("00-database-url", format!("     {}\n", DICTIONARY_URL)),
+        ("00-database-utf8", String::new()),
+        // Otherwise `dictd` would drop the non-alphanumeric characters of a query
+        ("00-database-allchars", String::new()),
+    ];
+
+    if format == ArticleFormat::Html {
+        headers.push((
+            "00-database-mime-header",
+            "     Content-Type: text/html; charset=utf-8\n".to_owned(),
+        ));
+    }
+
+    for (name, body) in headers {
+        let article = format!("{}\n{}", name, body);
+        words.push((name.to_owned(), dict.len(), article.len()));
+        dict.extend_from_slice(article.as_bytes());
+    }
+
+    let mut entries = 0;
+
+    for_each_entry(conn, |_, entry| {
+        let article = format!("{}\n", render_article(&entry, format));
+
+        for word in index_words(&entry) {
+            words.push((word, dict.len(), article.len()));
+        }
+
+        dict.extend_from_slice(article.as_bytes());
+
+        entries += 1;
+        Ok(())
+    })?;
+
+    // As `dictd` compares words in a UTF-8 database: without case, then by code point
+    words.sort_by_cached_key(|(word, offset, _)| (word.to_lowercase(), word.clone(), *offset));
+
+    let mut index = String::new();
+
+    for (word, offset, length) in &words {
+        // Tabs and newlines would break the index; headwords should never have them
+        let word = word.replace(['\t', '\n'], " ");
+        writeln!(index, "{}\t{}\t{}", word, b64(*offset), b64(*length)).unwrap();
+    }
+
+    Ok(Dictd {
+        index,
+        dict,
+        entries,
+    })
+}
+
+// Writes `base` plus ".index" and ".dict.dz"
+pub fn write_dictd(conn: &Connection, base: &Path, format: ArticleFormat) -> Result<usize, Error> {
+    let dictd = build_dictd(conn, format)?;
+
+    fs::write(dictionary_path(base, ".index"), &dictd.index)?;
+
+    let mut out = BufWriter::new(File::create(dictionary_path(base, ".dict.dz"))?);
+    write_dictzip(&dictd.dict, &mut out)?;
+    out.flush()?;
+
+    Ok(dictd.entries)
+}
+
+//
+// Private functions
+//
+
+fn b64(mut value: usize) -> String {
+    let mut digits = Vec::new();
+
+    loop {
+        digits.push(B64_DIGITS[value % 64]);
+        value /= 64;
+
+        if value == 0 {
+            break;
+        }
+    }
+
+    digits.reverse();
+    String::from_utf8(digits).unwrap()
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    fn from_b64(digits: &str) -> usize {
+        digits.bytes().fold(0, |value, digit| {
+            value * 64 + B64_DIGITS.iter().position(|d| *d == digit).unwrap()
+        })
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(b64(0), "A");
+        assert_eq!(b64(63), "/");
+        assert_eq!(b64(64), "BA");
+        assert_eq!(b64(4096 + 65), "BBB");
+        assert_eq!(from_b64(&b64(123_456_789)), 123_456_789);
+    }
+
+    #[test]
+    fn index() {
+        let conn = test_db(&[
+            (
+                3,
+                "<div><lang>a</lang> <hw><pa>آب</pa> <i>āb</i></hw>, s. Water; <i>lustre</i></div>",
+            ),
+            (3, "<div><hw><pa>باد</pa> <i>bād</i></hw>, Wind</div>"),
+        ]);
+
+        let dictd = build_dictd(&conn, ArticleFormat::Text).unwrap();
+        assert_eq!(dictd.entries, 2);
+
+        let lines: Vec<Vec<&str>> = dictd
+            .index
+            .lines()
+            .map(|line| line.split('\t').collect())
+            .collect();
+        let words: Vec<&str> = lines.iter().map(|fields| fields[0]).collect();
+        assert_eq!(
+            words,
+            vec![
+                "00-database-allchars",
+                "00-database-info",
+                "00-database-short",
+                "00-database-url",
+                "00-database-utf8",
+                "bād",
+                "āb",
+                "آب",
+                "باد",
+            ]
+        );
+
+        let article = |fields: &[&str]| {
+            let (offset, length) = (from_b64(fields[1]), from_b64(fields[2]));
+            String::from_utf8(dictd.dict[offset..offset + length].to_vec()).unwrap()
+        };
+        assert_eq!(
+            article(&lines[7]),
+            "آب āb [Arabic & Persian]\ns. Water; lustre\n"
+        );
+        assert_eq!(article(&lines[6]), article(&lines[7]));
+        assert_eq!(
+            article(&lines[2]),
+            "00-database-short\n     Steingass Persian-English Dictionary\n"
+        );
+
+        let html = build_dictd(&conn, ArticleFormat::Html).unwrap();
+        assert!(html.index.contains("00-database-mime-header\t"));
+    }
+}
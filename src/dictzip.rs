@@ -0,0 +1,187 @@
+use std::io::{self, Write};
+
+use flate2::{Compress, Compression, Crc, FlushCompress, Status};
+
+use crate::error::Error;
+
+//
+// Constants
+//
+
+// Uncompressed bytes per chunk, as chosen by `dictzip` itself; each chunk must compress to
+// fewer than 64 KiB
+pub const CHUNK_LENGTH: usize = 58315;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const FLAG_EXTRA: u8 = 0x04;
+const OS_UNIX: u8 = 3;
+
+//
+// Public functions
+//
+
+// Writes `data` as a dictzip file: a gzip stream whose header lists the compressed size of
+// each chunk, so that readers can seek to an article without inflating everything before it.
+// The modification time is left at zero to keep the output reproducible
+pub fn write_dictzip<W: Write>(data: &[u8], out: &mut W) -> Result<(), Error> {
+    let mut compressor = Compress::new(Compression::best(), false);
+    let mut chunks: Vec<Vec<u8>> = Vec::new();
+
+    // Even an empty file needs one chunk, to hold the final deflate block
+    let count = data.len().div_ceil(CHUNK_LENGTH).max(1);
+
+    for i in 0..count {
+        let start = i * CHUNK_LENGTH;
+        let end = (start + CHUNK_LENGTH).min(data.len());
+        let flush = if i + 1 == count {
+            FlushCompress::Finish
+        } else {
+            FlushCompress::Full
+        };
+
+        chunks.push(compress_chunk(&mut compressor, &data[start..end], flush)?);
+    }
+
+    let too_large = |what: &str| io::Error::other(format!("Dictzip {} too large", what));
+
+    let sizes = chunks
+        .iter()
+        .map(|chunk| u16::try_from(chunk.len()).map_err(|_| too_large("chunk")))
+        .collect::<Result<Vec<u16>, _>>()?;
+    let chunk_count = u16::try_from(sizes.len()).map_err(|_| too_large("file"))?;
+    let field_length = u16::try_from(6 + 2 * sizes.len()).map_err(|_| too_large("file"))?;
+    let extra_length = field_length
+        .checked_add(4)
+        .ok_or_else(|| too_large("file"))?;
+
+    // Header, with no modification time and "maximum compression" flagged
+    out.write_all(&GZIP_MAGIC)?;
+    out.write_all(&[8, FLAG_EXTRA, 0, 0, 0, 0, 2, OS_UNIX])?;
+
+    // The "RA" (random access) extra field
+    out.write_all(&extra_length.to_le_bytes())?;
+    out.write_all(b"RA")?;
+    out.write_all(&field_length.to_le_bytes())?;
+    out.write_all(&1_u16.to_le_bytes())?; // Version
+    out.write_all(&u16::try_from(CHUNK_LENGTH).unwrap().to_le_bytes())?;
+    out.write_all(&chunk_count.to_le_bytes())?;
+
+    for size in sizes {
+        out.write_all(&size.to_le_bytes())?;
+    }
+
+    for chunk in &chunks {
+        out.write_all(chunk)?;
+    }
+
+    let mut crc = Crc::new();
+    crc.update(data);
+
+    out.write_all(&crc.sum().to_le_bytes())?;
+    out.write_all(&crc.amount().to_le_bytes())?;
+
+    Ok(())
+}
+
+//
+// Private functions
+//
+
+// Every chunk but the last ends with a full flush, so that inflating can start at any of them
+fn compress_chunk(
+    compressor: &mut Compress,
+    chunk: &[u8],
+    flush: FlushCompress,
+) -> Result<Vec<u8>, Error> {
+    let mut compressed = Vec::with_capacity(chunk.len() + 64);
+    let start = compressor.total_in();
+
+    loop {
+        let consumed = usize::try_from(compressor.total_in() - start).unwrap();
+        let status = compressor
+            .compress_vec(&chunk[consumed..], &mut compressed, flush)
+            .map_err(io::Error::other)?;
+
+        let done = compressor.total_in() - start == chunk.len() as u64
+            && compressed.len() < compressed.capacity()
+            && (flush != FlushCompress::Finish || status == Status::StreamEnd);
+
+        if done {
+            return Ok(compressed);
+        }
+
+        compressed.reserve(chunk.len() / 2 + 64);
+    }
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+    use flate2::{Decompress, FlushDecompress};
+
+    use super::*;
+
+    fn dictzip(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_dictzip(data, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn gzip_compatible() {
+        let data: Vec<u8> = (0..200_000_u32)
+            .flat_map(|i| format!("{} ", i * 7919 % 10007).into_bytes())
+            .collect();
+        let out = dictzip(&data);
+
+        let mut inflated = Vec::new();
+        GzDecoder::new(out.as_slice())
+            .read_to_end(&mut inflated)
+            .unwrap();
+        assert_eq!(inflated, data);
+
+        // Reproducible
+        assert_eq!(dictzip(&data), out);
+
+        let mut inflated = Vec::new();
+        GzDecoder::new(dictzip(b"").as_slice())
+            .read_to_end(&mut inflated)
+            .unwrap();
+        assert!(inflated.is_empty());
+    }
+
+    #[test]
+    fn random_access() {
+        let data: Vec<u8> = (0..150_000_u32)
+            .flat_map(|i| format!("{}\n", i).into_bytes())
+            .collect();
+        let out = dictzip(&data);
+
+        let extra_length = usize::from(u16::from_le_bytes([out[10], out[11]]));
+        assert_eq!(&out[12..14], b"RA");
+        let chunk_count = usize::from(u16::from_le_bytes([out[20], out[21]]));
+        assert_eq!(chunk_count, data.len().div_ceil(CHUNK_LENGTH));
+
+        let sizes: Vec<usize> = (0..chunk_count)
+            .map(|i| usize::from(u16::from_le_bytes([out[22 + 2 * i], out[23 + 2 * i]])))
+            .collect();
+
+        // The third chunk inflates on its own
+        let offset = 12 + extra_length + sizes[0] + sizes[1];
+        let mut inflated = Vec::with_capacity(CHUNK_LENGTH);
+        Decompress::new(false)
+            .decompress_vec(
+                &out[offset..offset + sizes[2]],
+                &mut inflated,
+                FlushDecompress::Sync,
+            )
+            .unwrap();
+        assert_eq!(inflated, &data[2 * CHUNK_LENGTH..3 * CHUNK_LENGTH]);
+    }
+}
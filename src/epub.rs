@@ -0,0 +1,391 @@
+use std::fmt::Write as _;
+use std::io::{self, Seek, Write};
+
+use rusqlite::Connection;
+use zip::write::{SimpleFileOptions, ZipWriter};
+use zip::{CompressionMethod, DateTime};
+
+use crate::articles::{
+    ArticleFormat, DICTIONARY_AUTHOR, DICTIONARY_DESCRIPTION, DICTIONARY_TITLE, DICTIONARY_URL,
+    render_article,
+};
+use crate::error::Error;
+use crate::inflection::inflected_forms;
+use crate::xml::escape_xml;
+use crate::{Entry, for_each_entry};
+
+//
+// Constants
+//
+
+// Kindle readers handle many small content files better than one large one
+pub const ENTRIES_PER_FILE: usize = 1000;
+
+const IDENTIFIER: &str = "steingass-persian-english";
+
+// The namespace Amazon gives for its dictionary markup
+const KINDLE_NAMESPACE: &str =
+    "https://kindlegen.s3.amazonaws.com/AmazonKindlePublishingGuidelines.pdf";
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+//
+// Public functions
+//
+
+// An EPUB with Kindle dictionary markup (`idx:entry`, with `idx:infl` forms from
+// `inflection::inflected_forms`), ready for conversion to MOBI or KF8. Files are written in a
+// fixed order with fixed timestamps, so the same DB always gives the same bytes
+pub fn write_epub<W: Write + Seek>(conn: &Connection, out: W) -> Result<usize, Error> {
+    let mut zip = ZipWriter::new(out);
+    let mut files: Vec<(String, u16, u16)> = Vec::new(); // Name, first and last page
+    let mut body = String::new();
+    let mut pages: Option<(u16, u16)> = None;
+    let mut count = 0;
+
+    // The mimetype must come first, uncompressed
+    add_file(
+        &mut zip,
+        "mimetype",
+        b"application/epub+zip",
+        CompressionMethod::Stored,
+    )?;
+    add_file(
+        &mut zip,
+        "META-INF/container.xml",
+        CONTAINER_XML.as_bytes(),
+        CompressionMethod::Deflated,
+    )?;
+    add_file(
+        &mut zip,
+        "OEBPS/title.xhtml",
+        title_page().as_bytes(),
+        CompressionMethod::Deflated,
+    )?;
+
+    for_each_entry(conn, |_, entry| {
+        body.push_str(&epub_entry(&entry));
+        pages = Some(pages.map_or((entry.page, entry.page), |(first, _)| (first, entry.page)));
+        count += 1;
+
+        if count % ENTRIES_PER_FILE == 0 {
+            flush_entries(&mut zip, &mut files, &mut body, pages.take())?;
+        }
+
+        Ok(())
+    })?;
+
+    flush_entries(&mut zip, &mut files, &mut body, pages.take())?;
+
+    add_file(
+        &mut zip,
+        "OEBPS/content.opf",
+        package(&files).as_bytes(),
+        CompressionMethod::Deflated,
+    )?;
+    add_file(
+        &mut zip,
+        "OEBPS/toc.ncx",
+        ncx(&files).as_bytes(),
+        CompressionMethod::Deflated,
+    )?;
+
+    zip.finish().map_err(io::Error::other)?;
+
+    Ok(count)
+}
+
+//
+// Private functions
+//
+
+fn add_file<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    name: &str,
+    content: &[u8],
+    method: CompressionMethod,
+) -> Result<(), Error> {
+    let options = SimpleFileOptions::default()
+        .compression_method(method)
+        .last_modified_time(DateTime::default())
+        .unix_permissions(0o644);
+
+    zip.start_file(name, options).map_err(io::Error::other)?;
+    zip.write_all(content)?;
+
+    Ok(())
+}
+
+// Writes the entries collected so far as the next content file
+fn flush_entries<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    files: &mut Vec<(String, u16, u16)>,
+    body: &mut String,
+    pages: Option<(u16, u16)>,
+) -> Result<(), Error> {
+    let Some((first, last)) = pages else {
+        return Ok(());
+    };
+
+    let name = format!("entries-{:04}.xhtml", files.len() + 1);
+    let title = format!("Pages {}–{}", first, last);
+
+    add_file(
+        zip,
+        &format!("OEBPS/{}", name),
+        xhtml(&title, &format!("<mbp:frameset>\n{}</mbp:frameset>", body)).as_bytes(),
+        CompressionMethod::Deflated,
+    )?;
+
+    files.push((name, first, last));
+    body.clear();
+
+    Ok(())
+}
+
+fn epub_entry(entry: &Entry) -> String {
+    let headword = entry.headword_persian.trim();
+    let mut html = String::from("<idx:entry name=\"persian\" scriptable=\"yes\" spell=\"yes\">\n");
+
+    write!(html, "<idx:orth value=\"{}\">", escape_xml(headword)).unwrap();
+
+    let forms = inflected_forms(headword);
+
+    if !forms.is_empty() {
+        html.push_str("<idx:infl>");
+
+        for form in forms {
+            write!(html, "<idx:iform value=\"{}\"/>", escape_xml(&form)).unwrap();
+        }
+
+        html.push_str("</idx:infl>");
+    }
+
+    writeln!(
+        html,
+        "</idx:orth>\n{}\n</idx:entry>\n<hr/>",
+        render_article(entry, ArticleFormat::Html)
+    )
+    .unwrap();
+
+    html
+}
+
+fn xhtml(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:idx=\"{ns}\" xmlns:mbp=\"{ns}\" \
+         xml:lang=\"en\">\n<head>\n<meta http-equiv=\"Content-Type\" \
+         content=\"text/html; charset=utf-8\"/>\n<title>{}</title>\n</head>\n<body>\n{}\n\
+         </body>\n</html>\n",
+        escape_xml(title),
+        body,
+        ns = KINDLE_NAMESPACE
+    )
+}
+
+fn title_page() -> String {
+    xhtml(
+        DICTIONARY_TITLE,
+        &format!(
+            "<h1>{}</h1>\n<p>{}</p>\n<p>{}</p>",
+            escape_xml(DICTIONARY_TITLE),
+            escape_xml(DICTIONARY_AUTHOR),
+            escape_xml(DICTIONARY_DESCRIPTION)
+        ),
+    )
+}
+
+fn package(files: &[(String, u16, u16)]) -> String {
+    let mut manifest = String::from(
+        "    <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n    \
+         <item id=\"title\" href=\"title.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+    );
+    let mut spine = String::from("    <itemref idref=\"title\"/>\n");
+
+    for (i, (name, _, _)) in files.iter().enumerate() {
+        writeln!(
+            manifest,
+            "    <item id=\"entries-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>",
+            i + 1,
+            name
+        )
+        .unwrap();
+        writeln!(spine, "    <itemref idref=\"entries-{}\"/>", i + 1).unwrap();
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"2.0\" \
+         unique-identifier=\"uid\">\n  \
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+         xmlns:opf=\"http://www.idpf.org/2007/opf\">\n    \
+         <dc:identifier id=\"uid\">{}</dc:identifier>\n    <dc:title>{}</dc:title>\n    \
+         <dc:creator opf:role=\"aut\">{}</dc:creator>\n    <dc:language>fa</dc:language>\n    \
+         <dc:source>{}</dc:source>\n    <x-metadata>\n      \
+         <DictionaryInLanguage>fa</DictionaryInLanguage>\n      \
+         <DictionaryOutLanguage>en</DictionaryOutLanguage>\n      \
+         <DefaultLookupIndex>persian</DefaultLookupIndex>\n    </x-metadata>\n  \
+         </metadata>\n  <manifest>\n{}  </manifest>\n  <spine toc=\"ncx\">\n{}  </spine>\n\
+         </package>\n",
+        IDENTIFIER,
+        escape_xml(DICTIONARY_TITLE),
+        escape_xml(DICTIONARY_AUTHOR),
+        escape_xml(DICTIONARY_URL),
+        manifest,
+        spine
+    )
+}
+
+fn ncx(files: &[(String, u16, u16)]) -> String {
+    let mut points = String::new();
+
+    for (i, (name, first, last)) in files.iter().enumerate() {
+        writeln!(
+            points,
+            "    <navPoint id=\"entries-{n}\" playOrder=\"{n}\">\n      \
+             <navLabel><text>Pages {}–{}</text></navLabel>\n      \
+             <content src=\"{}\"/>\n    </navPoint>",
+            first,
+            last,
+            name,
+            n = i + 1
+        )
+        .unwrap();
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">\n  <head>\n    \
+         <meta name=\"dtb:uid\" content=\"{}\"/>\n  </head>\n  \
+         <docTitle><text>{}</text></docTitle>\n  <navMap>\n{}  </navMap>\n</ncx>\n",
+        IDENTIFIER,
+        escape_xml(DICTIONARY_TITLE),
+        points
+    )
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::{ensure_table, insert_row, parse_entry};
+
+    fn epub(conn: &Connection) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        write_epub(conn, &mut out).unwrap();
+        out.into_inner()
+    }
+
+    fn read(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut text = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    // Content files have an HTML5 doctype
+    fn parse_xhtml(text: &str) -> roxmltree::Document<'_> {
+        let options = roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..roxmltree::ParsingOptions::default()
+        };
+        roxmltree::Document::parse_with_options(text, options).unwrap()
+    }
+
+    #[test]
+    fn package() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_table(&conn).unwrap();
+
+        let first = parse_entry(
+            200,
+            1,
+            "<div><lang>a</lang> <hw><pa>بنده</pa> <i>banda</i></hw>, s. A servant</div>"
+                .to_owned(),
+        )
+        .unwrap();
+        let other = parse_entry(
+            200,
+            2,
+            "<div><hw><pa>کتاب</pa> <i>kitāb</i></hw>, A book &amp; <i>volume</i></div>".to_owned(),
+        )
+        .unwrap();
+
+        // Enough entries, ten to a page, to fill one content file and start another
+        for i in 0..=ENTRIES_PER_FILE {
+            let mut entry = if i == 0 { first.clone() } else { other.clone() };
+            entry.page = 200 + u16::try_from(i / 10).unwrap();
+            entry.position = i % 10 + 1;
+            entry.uid = format!("{:04}-{:03}-00000000", entry.page, entry.position);
+            insert_row(&conn, entry).unwrap();
+        }
+
+        let bytes = epub(&conn);
+        assert_eq!(&bytes[30..38], b"mimetype");
+        assert_eq!(&bytes[38..58], b"application/epub+zip");
+
+        // The same DB gives the same file
+        assert_eq!(epub(&conn), bytes);
+
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+
+        let opf = read(&mut archive, "OEBPS/content.opf");
+        let doc = roxmltree::Document::parse(&opf).unwrap();
+        let items: Vec<_> = doc
+            .descendants()
+            .filter(|n| n.has_tag_name("itemref"))
+            .filter_map(|n| n.attribute("idref"))
+            .collect();
+        assert_eq!(items, vec!["title", "entries-1", "entries-2"]);
+        assert!(
+            doc.descendants()
+                .any(|n| n.has_tag_name("DictionaryInLanguage") && n.text() == Some("fa"))
+        );
+
+        let ncx = read(&mut archive, "OEBPS/toc.ncx");
+        assert!(ncx.contains("<text>Pages 200–299</text>"));
+        assert!(ncx.contains("<text>Pages 300–300</text>"));
+
+        let first = read(&mut archive, "OEBPS/entries-0001.xhtml");
+        let doc = parse_xhtml(&first);
+        let entries: Vec<_> = doc
+            .descendants()
+            .filter(|n| n.tag_name().name() == "entry")
+            .collect();
+        assert_eq!(entries.len(), ENTRIES_PER_FILE);
+        assert_eq!(entries[0].tag_name().namespace(), Some(KINDLE_NAMESPACE));
+
+        let orth = entries[0]
+            .descendants()
+            .find(|n| n.tag_name().name() == "orth")
+            .unwrap();
+        assert_eq!(orth.attribute("value"), Some("بنده"));
+
+        assert!(
+            orth.descendants()
+                .filter(|n| n.tag_name().name() == "iform")
+                .any(|n| n.attribute("value") == Some("بندگان"))
+        );
+
+        let last = read(&mut archive, "OEBPS/entries-0002.xhtml");
+        parse_xhtml(&last);
+    }
+}
@@ -0,0 +1,224 @@
+use std::fmt;
+
+//
+// Type definitions
+//
+
+// Where in the dictionary an error arose, as far as is known
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Default)]
+pub struct Location {
+    pub page: Option<u16>,
+    pub entry: Option<usize>, // 1-based position among the results on the page
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Network {
+        location: Location,
+        source: reqwest::Error,
+    },
+    NotInStore {
+        location: Location,
+    },
+    MissingElement {
+        location: Location,
+        selector: &'static str,
+    },
+    UnknownLanguage {
+        location: Location,
+        tag: String,
+    },
+    Parse {
+        location: Location,
+        message: String,
+    },
+    Database {
+        location: Location,
+        source: rusqlite::Error,
+    },
+    Migration {
+        location: Location,
+        version: u32,
+        description: &'static str,
+        source: rusqlite::Error,
+    },
+    Io {
+        location: Location,
+        source: std::io::Error,
+    },
+}
+
+impl Location {
+    #[must_use]
+    pub const fn page(page: u16) -> Self {
+        Self {
+            page: Some(page),
+            entry: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn entry(page: u16, entry: usize) -> Self {
+        Self {
+            page: Some(page),
+            entry: Some(entry),
+        }
+    }
+}
+
+impl Error {
+    #[must_use]
+    pub const fn location(&self) -> Location {
+        match self {
+            Self::Network { location, .. }
+            | Self::NotInStore { location }
+            | Self::MissingElement { location, .. }
+            | Self::UnknownLanguage { location, .. }
+            | Self::Parse { location, .. }
+            | Self::Database { location, .. }
+            | Self::Migration { location, .. }
+            | Self::Io { location, .. } => *location,
+        }
+    }
+
+    // Fills in whatever part of the location is not yet known
+    #[must_use]
+    pub fn at(mut self, at: Location) -> Self {
+        let location = match &mut self {
+            Self::Network { location, .. }
+            | Self::NotInStore { location }
+            | Self::MissingElement { location, .. }
+            | Self::UnknownLanguage { location, .. }
+            | Self::Parse { location, .. }
+            | Self::Database { location, .. }
+            | Self::Migration { location, .. }
+            | Self::Io { location, .. } => location,
+        };
+
+        location.page = location.page.or(at.page);
+        location.entry = location.entry.or(at.entry);
+
+        self
+    }
+
+    #[must_use]
+    pub const fn missing(selector: &'static str) -> Self {
+        Self::MissingElement {
+            location: Location {
+                page: None,
+                entry: None,
+            },
+            selector,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.page, self.entry) {
+            (Some(page), Some(entry)) => write!(f, "p. {}, entry {}", page, entry),
+            (Some(page), None) => write!(f, "p. {}", page),
+            (None, Some(entry)) => write!(f, "entry {}", entry),
+            (None, None) => write!(f, "unknown location"),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = self.location();
+
+        if location != Location::default() {
+            write!(f, "{}: ", location)?;
+        }
+
+        match self {
+            Self::Network { source, .. } => write!(f, "Network error: {}", source),
+            Self::NotInStore { .. } => write!(f, "Page is not in the page store"),
+            Self::MissingElement { selector, .. } => {
+                write!(f, "Missing element matching `{}`", selector)
+            }
+            Self::UnknownLanguage { tag, .. } => write!(f, "Unrecognized language: {}", tag),
+            Self::Parse { message, .. } => write!(f, "Parse error: {}", message),
+            Self::Database { source, .. } => write!(f, "Database error: {}", source),
+            Self::Migration {
+                version,
+                description,
+                source,
+                ..
+            } => write!(
+                f,
+                "Migration {} ({}) failed: {}",
+                version, description, source
+            ),
+            Self::Io { source, .. } => write!(f, "I/O error: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Network { source, .. } => Some(source),
+            Self::Database { source, .. } | Self::Migration { source, .. } => Some(source),
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(source: rusqlite::Error) -> Self {
+        Self::Database {
+            location: Location::default(),
+            source,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(source: std::io::Error) -> Self {
+        Self::Io {
+            location: Location::default(),
+            source,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(source: reqwest::Error) -> Self {
+        Self::Network {
+            location: Location::default(),
+            source,
+        }
+    }
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let error = Error::missing("hw pa");
+        assert_eq!(error.to_string(), "Missing element matching `hw pa`");
+
+        let error = error.at(Location::entry(5, 3));
+        assert_eq!(error.location(), Location::entry(5, 3));
+        assert_eq!(
+            error.to_string(),
+            "p. 5, entry 3: Missing element matching `hw pa`"
+        );
+
+        // A known location is not overwritten
+        let error = Error::NotInStore {
+            location: Location::page(8),
+        }
+        .at(Location::entry(9, 1));
+        assert_eq!(error.location(), Location::entry(8, 1));
+    }
+}
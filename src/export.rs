@@ -0,0 +1,338 @@
+use std::fmt;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use rusqlite::Connection;
+
+use crate::error::Error;
+use crate::langs::Lang;
+use crate::{ENTRY_COLUMNS, Entry, read_entry};
+
+//
+// Type definitions
+//
+
+// A column that can be selected for a table export
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub enum Field {
+    Id,
+    Uid,
+    Page,
+    Position,
+    Lang,
+    LangCode,
+    HeadwordFull,
+    HeadwordPersian,
+    HeadwordLatin,
+    Definitions,
+    Abjad,
+    Root,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FieldParseError(pub String);
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Default)]
+pub enum TableFormat {
+    #[default]
+    Tsv, // Tabs, newlines and backslashes escaped with backslashes
+    Csv, // As in RFC 4180
+}
+
+// Which entries to export; every condition left as None matches all entries
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+pub struct Filter {
+    pub lang: Option<Lang>,
+    pub pages: Option<RangeInclusive<u16>>,
+    pub abjad: Option<RangeInclusive<u32>>,
+}
+
+//
+// Constants
+//
+
+pub const FIELDS: [Field; 12] = [
+    Field::Id,
+    Field::Uid,
+    Field::Page,
+    Field::Position,
+    Field::Lang,
+    Field::LangCode,
+    Field::HeadwordFull,
+    Field::HeadwordPersian,
+    Field::HeadwordLatin,
+    Field::Definitions,
+    Field::Abjad,
+    Field::Root,
+];
+
+// The columns of `write_tsv`
+pub const DEFAULT_FIELDS: [Field; 8] = [
+    Field::Id,
+    Field::Uid,
+    Field::Page,
+    Field::Lang,
+    Field::HeadwordFull,
+    Field::HeadwordPersian,
+    Field::HeadwordLatin,
+    Field::Definitions,
+];
+
+//
+// Implementations
+//
+
+impl Field {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Uid => "uid",
+            Self::Page => "page",
+            Self::Position => "position",
+            Self::Lang => "lang",
+            Self::LangCode => "lang_code",
+            Self::HeadwordFull => "headword_full",
+            Self::HeadwordPersian => "headword_persian",
+            Self::HeadwordLatin => "headword_latin",
+            Self::Definitions => "definitions",
+            Self::Abjad => "abjad",
+            Self::Root => "root",
+        }
+    }
+
+    // Missing values (an unmarked language's code, or a root) are written as empty strings
+    #[must_use]
+    pub fn value(self, id: i64, entry: &Entry) -> String {
+        match self {
+            Self::Id => id.to_string(),
+            Self::Uid => entry.uid.clone(),
+            Self::Page => entry.page.to_string(),
+            Self::Position => entry.position.to_string(),
+            Self::Lang => entry.lang.as_str().to_owned(),
+            Self::LangCode => entry.lang.code().unwrap_or_default().to_owned(),
+            Self::HeadwordFull => entry.headword_full.clone(),
+            Self::HeadwordPersian => entry.headword_persian.clone(),
+            Self::HeadwordLatin => entry.headword_latin.clone(),
+            Self::Definitions => entry.definitions.clone(),
+            Self::Abjad => entry.abjad.to_string(),
+            Self::Root => entry.root.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for FieldParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unrecognized field: {}", self.0)
+    }
+}
+
+impl std::error::Error for FieldParseError {}
+
+impl FromStr for Field {
+    type Err = FieldParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FIELDS
+            .into_iter()
+            .find(|field| field.as_str() == s.trim())
+            .ok_or_else(|| FieldParseError(s.to_owned()))
+    }
+}
+
+//
+// Public functions
+//
+
+pub fn write_tsv<W: Write>(conn: &Connection, out: &mut W) -> Result<usize, Error> {
+    write_table(
+        conn,
+        out,
+        TableFormat::Tsv,
+        &DEFAULT_FIELDS,
+        &Filter::default(),
+    )
+}
+
+// A header row of field names, then one row per matching entry, in ID order
+pub fn write_table<W: Write>(
+    conn: &Connection,
+    out: &mut W,
+    format: TableFormat,
+    fields: &[Field],
+    filter: &Filter,
+) -> Result<usize, Error> {
+    let (separator, escape): (&str, fn(&str) -> String) = match format {
+        TableFormat::Tsv => ("\t", escape_tsv),
+        TableFormat::Csv => (",", escape_csv),
+    };
+
+    let header: Vec<String> = fields.iter().map(|field| escape(field.as_str())).collect();
+    write!(out, "{}{}", header.join(separator), line_end(format))?;
+
+    let mut count = 0;
+
+    for_each_filtered(conn, filter, |id, entry| {
+        let row: Vec<String> = fields
+            .iter()
+            .map(|field| escape(&field.value(id, &entry)))
+            .collect();
+        write!(out, "{}{}", row.join(separator), line_end(format))?;
+
+        count += 1;
+        Ok(())
+    })?;
+
+    Ok(count)
+}
+
+// As `for_each_entry`, for the entries that pass the filter; images and senses are not loaded
+pub fn for_each_filtered<F>(conn: &Connection, filter: &Filter, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(i64, Entry) -> Result<(), Error>,
+{
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM entries
+        WHERE (?1 IS NULL OR lang = ?1)
+            AND (?2 IS NULL OR page BETWEEN ?2 AND ?3)
+            AND (?4 IS NULL OR abjad BETWEEN ?4 AND ?5)
+        ORDER BY id",
+        ENTRY_COLUMNS
+    ))?;
+
+    let mut rows = stmt.query((
+        filter.lang.map(Lang::as_str),
+        filter.pages.as_ref().map(|pages| *pages.start()),
+        filter.pages.as_ref().map(|pages| *pages.end()),
+        filter.abjad.as_ref().map(|abjad| *abjad.start()),
+        filter.abjad.as_ref().map(|abjad| *abjad.end()),
+    ))?;
+
+    while let Some(row) = rows.next()? {
+        f(row.get(0)?, read_entry(row)?)?;
+    }
+
+    Ok(())
+}
+
+//
+// Private functions
+//
+
+const fn line_end(format: TableFormat) -> &'static str {
+    match format {
+        TableFormat::Tsv => "\n",
+        TableFormat::Csv => "\r\n",
+    }
+}
+
+fn escape_tsv(input: &str) -> String {
+    input
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+// Quotes only the values that need it
+fn escape_csv(input: &str) -> String {
+    if input.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", input.replace('"', "\"\""))
+    } else {
+        input.to_owned()
+    }
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ensure_table, insert_row, test_db};
+
+    #[test]
+    fn tsv() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_table(&conn).unwrap();
+
+        let entry = Entry {
+            page: 3,
+            position: 1,
+            uid: "0003-001-00000000".to_owned(),
+            raw_html: "<div></div>".to_owned(),
+            lang: Lang::PersianArabic,
+            headword_full: "آب āb".to_owned(),
+            headword_persian: "آب".to_owned(),
+            headword_latin: "āb".to_owned(),
+            definitions: "Water;\ta river".to_owned(),
+            abjad: 3,
+            headword_search: "اب".to_owned(),
+            headword_latin_search: "ab".to_owned(),
+            root: None,
+            images: Vec::new(),
+            senses: Vec::new(),
+        };
+        insert_row(&conn, entry).unwrap();
+
+        let mut out = Vec::new();
+        let count = write_tsv(&conn, &mut out).unwrap();
+        assert_eq!(count, 1);
+
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1],
+            "1\t0003-001-00000000\t3\tArabic & Persian\tآب āb\tآب\tāb\tWater;\\ta river"
+        );
+    }
+
+    #[test]
+    fn csv_filtered() {
+        let conn = test_db(&[
+            (
+                3,
+                "<div><lang>a</lang> <hw><pa>آب</pa> <i>āb</i></hw>, s. Water, \"rain\"</div>",
+            ),
+            (3, "<div><hw><pa>باد</pa> <i>bād</i></hw>, Wind</div>"),
+            (
+                4,
+                "<div><lang>a</lang> <hw><pa>باب</pa> <i>bāb</i></hw>, A door</div>",
+            ),
+        ]);
+
+        let fields: Vec<Field> = "page, lang_code,definitions"
+            .split(',')
+            .map(|name| name.parse().unwrap())
+            .collect();
+        assert_eq!("hw".parse::<Field>(), Err(FieldParseError("hw".to_owned())));
+
+        let filter = Filter {
+            lang: Some(Lang::PersianArabic),
+            pages: Some(3..=3),
+            abjad: None,
+        };
+
+        let mut out = Vec::new();
+        let count = write_table(&conn, &mut out, TableFormat::Csv, &fields, &filter).unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "page,lang_code,definitions\r\n3,a,\"s. Water, \"\"rain\"\"\"\r\n"
+        );
+
+        // آب = 1 + 2; باد = 2 + 1 + 4; باب = 2 + 1 + 2
+        let filter = Filter {
+            abjad: Some(4..=7),
+            ..Filter::default()
+        };
+
+        let mut out = Vec::new();
+        let count = write_table(&conn, &mut out, TableFormat::Tsv, &[Field::Uid], &filter).unwrap();
+        assert_eq!(count, 2);
+    }
+}
@@ -0,0 +1,118 @@
+use rusqlite::Connection;
+
+use crate::error::Error;
+use crate::migrations::{ENTRY_INDEXES, FTS_REBUILD, FTS_TABLES, FTS_TRIGGERS, UID_INDEX};
+
+//
+// Public functions
+//
+
+pub fn rebuild_fts(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(FTS_REBUILD)?;
+    Ok(())
+}
+
+// The same tables, indexes and triggers as a SQL script, for a DB (e.g., on D1) that already
+// holds the `entries` table
+#[must_use]
+pub fn fts_script() -> String {
+    let mut script = String::from("-- Full-text tables, indexes and triggers for `entries`\n");
+
+    for section in [
+        FTS_TABLES,
+        FTS_REBUILD,
+        ENTRY_INDEXES,
+        UID_INDEX,
+        FTS_TRIGGERS,
+    ] {
+        script.push('\n');
+        script.push_str(section);
+    }
+
+    script
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ensure_table, insert_row, parse_entry, update_row};
+
+    fn matches(conn: &Connection, table: &str, query: &str) -> Vec<i64> {
+        conn.prepare(&format!(
+            "SELECT rowid FROM {} WHERE {} MATCH ? ORDER BY rowid",
+            table, table
+        ))
+        .unwrap()
+        .query_map([query], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    #[test]
+    fn sync() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_table(&conn).unwrap();
+
+        let entry = parse_entry(
+            3,
+            1,
+            "<div><hw><pa>آب</pa> <i>āb</i></hw>, Water; lustre</div>".to_owned(),
+        )
+        .unwrap();
+        let id = insert_row(&conn, entry.clone()).unwrap();
+
+        assert_eq!(matches(&conn, "ft_def", "lustre"), vec![id]);
+        assert_eq!(matches(&conn, "ft_all", "lustre"), vec![id]);
+        assert_eq!(matches(&conn, "ft_per", "آب"), vec![id]);
+
+        let updated = crate::Entry {
+            definitions: "Water; splendour".to_owned(),
+            ..entry
+        };
+        update_row(&conn, id, &updated).unwrap();
+
+        assert!(matches(&conn, "ft_def", "lustre").is_empty());
+        assert_eq!(matches(&conn, "ft_def", "splendour"), vec![id]);
+
+        conn.execute("DELETE FROM entries WHERE id = ?", [id])
+            .unwrap();
+        assert!(matches(&conn, "ft_def", "splendour").is_empty());
+        assert!(matches(&conn, "ft_hw", "āb").is_empty());
+    }
+
+    #[test]
+    fn script() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE entries (
+                id INTEGER NOT NULL PRIMARY KEY,
+                page INTEGER NOT NULL,
+                raw_html TEXT NOT NULL,
+                lang TEXT NOT NULL,
+                headword_full TEXT NOT NULL,
+                headword_persian TEXT NOT NULL,
+                headword_latin TEXT NOT NULL,
+                definitions TEXT NOT NULL,
+                uid TEXT NOT NULL,
+                abjad INTEGER NOT NULL
+            );
+            INSERT INTO entries VALUES (
+                7, 3, '', 'Persian', 'آب āb', 'آب', 'āb', 'Water', '0003-001-00000000', 3
+            );",
+        )
+        .unwrap();
+
+        let script = fts_script();
+        conn.execute_batch(&script).unwrap();
+
+        // Running it twice does no harm
+        conn.execute_batch(&script).unwrap();
+
+        assert_eq!(matches(&conn, "ft_all", "water"), vec![7]);
+    }
+}
@@ -0,0 +1,295 @@
+use rusqlite::Connection;
+
+use crate::error::Error;
+use crate::normalize::{fold_latin, normalize_for_search};
+use crate::query::Script;
+
+//
+// Type definitions
+//
+
+// A headword close to the one looked up, with the entries that have it
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Default)]
+pub struct Suggestion {
+    pub distance: u32,
+    pub headword: String, // As stored, from the first entry with this spelling
+    pub ids: Vec<i64>,
+}
+
+// A BK-tree over the normalized (or folded) headwords of one script
+#[derive(Debug, Clone)]
+pub struct FuzzyIndex {
+    script: Script,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    key: Vec<char>,
+    headword: String,
+    ids: Vec<i64>,
+    children: Vec<(u32, usize)>, // Distance from this node, and index of the child
+}
+
+//
+// Constants
+//
+
+pub const EDIT_COST: u32 = 2;
+pub const CONFUSABLE_COST: u32 = 1;
+
+// Letters that are easily mistaken for one another, in reading or in spelling by ear
+const CONFUSABLE_GROUPS: [&[char]; 4] = [
+    &['\u{0633}', '\u{0635}', '\u{062B}'], // Sin, sad, theh
+    &['\u{0632}', '\u{0630}', '\u{0636}', '\u{0638}'], // Zain, thal, dad, zah
+    &['\u{062A}', '\u{0637}'],             // Teh, tah
+    &['\u{062D}', '\u{0647}'],             // Hah, heh
+];
+
+//
+// Implementations
+//
+
+impl FuzzyIndex {
+    // Indexes every headword in the DB in the given script
+    pub fn build(conn: &Connection, script: Script) -> Result<Self, Error> {
+        let sql = match script {
+            Script::Persian => {
+                "SELECT id, headword_search, headword_persian FROM entries ORDER BY id"
+            }
+            Script::Latin => {
+                "SELECT id, headword_latin_search, headword_latin FROM entries ORDER BY id"
+            }
+        };
+
+        let mut index = Self {
+            script,
+            nodes: Vec::new(),
+        };
+
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key: String = row.get(1)?;
+
+            if !key.is_empty() {
+                index.insert(row.get(0)?, &key, row.get(2)?);
+            }
+        }
+
+        Ok(index)
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Headwords within `max_distance` of the query, nearest first
+    #[must_use]
+    pub fn suggest(&self, query: &str, max_distance: u32, limit: usize) -> Vec<Suggestion> {
+        let key: Vec<char> = match self.script {
+            Script::Persian => normalize_for_search(query).chars().collect(),
+            Script::Latin => fold_latin(query).chars().collect(),
+        };
+
+        if key.is_empty() || self.nodes.is_empty() {
+            return Vec::new();
+        }
+
+        let mut found = Vec::new();
+        let mut pending = vec![0];
+
+        while let Some(i) = pending.pop() {
+            let node = &self.nodes[i];
+            let distance = edit_distance(&key, &node.key);
+
+            if distance <= max_distance {
+                found.push(Suggestion {
+                    distance,
+                    headword: node.headword.clone(),
+                    ids: node.ids.clone(),
+                });
+            }
+
+            // By the triangle inequality, nothing further down any other branch can be in range
+            let low = distance.saturating_sub(max_distance);
+            let high = distance + max_distance;
+
+            pending.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| (low..=high).contains(d))
+                    .map(|(_, child)| *child),
+            );
+        }
+
+        found.sort_by(|a, b| (a.distance, &a.ids).cmp(&(b.distance, &b.ids)));
+        found.truncate(limit);
+        found
+    }
+
+    fn insert(&mut self, id: i64, key: &str, headword: String) {
+        let key: Vec<char> = key.chars().collect();
+
+        if self.nodes.is_empty() {
+            self.nodes.push(Node::new(id, key, headword));
+            return;
+        }
+
+        let mut i = 0;
+
+        loop {
+            let distance = edit_distance(&key, &self.nodes[i].key);
+
+            if distance == 0 {
+                self.nodes[i].ids.push(id);
+                return;
+            }
+
+            if let Some(&(_, child)) = self.nodes[i].children.iter().find(|(d, _)| *d == distance) {
+                i = child;
+                continue;
+            }
+
+            let child = self.nodes.len();
+            self.nodes.push(Node::new(id, key, headword));
+            self.nodes[i].children.push((distance, child));
+            return;
+        }
+    }
+}
+
+impl Node {
+    fn new(id: i64, key: Vec<char>, headword: String) -> Self {
+        Self {
+            key,
+            headword,
+            ids: vec![id],
+            children: Vec::new(),
+        }
+    }
+}
+
+//
+// Public functions
+//
+
+// Levenshtein distance, in which swapping one confusable letter for another costs less than
+// any other edit
+#[must_use]
+pub fn edit_distance(a: &[char], b: &[char]) -> u32 {
+    let mut previous: Vec<u32> = (0..=b.len())
+        .map(|j| u32::try_from(j).unwrap_or(u32::MAX) * EDIT_COST)
+        .collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for &ca in a {
+        current[0] = previous[0] + EDIT_COST;
+
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + substitution_cost(ca, cb);
+            let deletion = previous[j + 1] + EDIT_COST;
+            let insertion = current[j] + EDIT_COST;
+
+            current[j + 1] = substitution.min(deletion).min(insertion);
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+// Builds an index for a single lookup; keep a `FuzzyIndex` around for more than one
+pub fn suggest(
+    conn: &Connection,
+    script: Script,
+    query: &str,
+    max_distance: u32,
+    limit: usize,
+) -> Result<Vec<Suggestion>, Error> {
+    Ok(FuzzyIndex::build(conn, script)?.suggest(query, max_distance, limit))
+}
+
+//
+// Private functions
+//
+
+fn substitution_cost(a: char, b: char) -> u32 {
+    if a == b {
+        0
+    } else if CONFUSABLE_GROUPS
+        .iter()
+        .any(|group| group.contains(&a) && group.contains(&b))
+    {
+        CONFUSABLE_COST
+    } else {
+        EDIT_COST
+    }
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    fn distance(a: &str, b: &str) -> u32 {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        edit_distance(&a, &b)
+    }
+
+    #[test]
+    fn costs() {
+        assert_eq!(distance("صبر", "صبر"), 0);
+        assert_eq!(distance("سبر", "صبر"), CONFUSABLE_COST);
+        assert_eq!(distance("ذلیل", "ظلیل"), CONFUSABLE_COST);
+        assert_eq!(distance("تاق", "طاق"), CONFUSABLE_COST);
+        assert_eq!(distance("بهر", "بحر"), CONFUSABLE_COST);
+        assert_eq!(distance("کبر", "صبر"), EDIT_COST);
+        assert_eq!(distance("صبر", "صبور"), EDIT_COST);
+        assert_eq!(distance("khana", "khan"), EDIT_COST);
+        assert_eq!(distance("", "ab"), 2 * EDIT_COST);
+    }
+
+    #[test]
+    fn suggestions() {
+        let conn = test_db(&[
+            (
+                760,
+                "<div><hw><pa>صبر</pa> <i>ṣabr</i></hw>, Patience</div>",
+            ),
+            (760, "<div><hw><pa>صبح</pa> <i>ṣubḥ</i></hw>, Morning</div>"),
+            (760, "<div><hw><pa>طاق</pa> <i>ṭāq</i></hw>, An arch</div>"),
+            (760, "<div><hw><pa>صبر</pa> <i>ṣabir</i></hw>, Aloes</div>"),
+        ]);
+
+        let index = FuzzyIndex::build(&conn, Script::Persian).unwrap();
+        assert_eq!(index.len(), 3);
+
+        let found = index.suggest("سبر", 3, 10);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].headword, "صبر");
+        assert_eq!(found[0].distance, CONFUSABLE_COST);
+        assert_eq!(found[0].ids, vec![1, 4]);
+        assert_eq!(found[1].headword, "صبح");
+
+        assert_eq!(index.suggest("تاق", 2, 10)[0].headword, "طاق");
+        assert!(index.suggest("", 2, 10).is_empty());
+
+        let found = suggest(&conn, Script::Latin, "sobh", 2, 10).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].headword, "*ṣubḥ*");
+    }
+}
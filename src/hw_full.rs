@@ -1,10 +1,14 @@
-use crate::{clean_simple, pandoc};
+use crate::error::Error;
+use crate::{clean_simple, to_markdown};
 use scraper::{Html, Selector};
 
-pub fn select_full_headword(parsed: &Html) -> Result<String, anyhow::Error> {
+pub fn select_full_headword(parsed: &Html) -> Result<String, Error> {
     let selector = Selector::parse("hw").unwrap();
-    let hw_full = parsed.select(&selector).next().unwrap();
-    let hw_full_text = pandoc(&hw_full.html())?;
+    let hw_full = parsed
+        .select(&selector)
+        .next()
+        .ok_or_else(|| Error::missing("hw"))?;
+    let hw_full_text = to_markdown(&hw_full.html());
     let cleaned = clean_hw_full(&hw_full_text);
 
     Ok(cleaned)
@@ -130,9 +134,9 @@ mod tests {
         }
     }
 
-    // This takes about 35 minutes to run; it has run successfully
-    // #[test]
-    fn _values_slow() {
+    // Regenerates every value from the raw HTML
+    #[test]
+    fn values_regen() {
         let conn = Connection::open("entries.sqlite").unwrap();
         let mut stmt = conn
             .prepare("SELECT id, raw_html, headword_full FROM entries")
@@ -1,12 +1,13 @@
-use crate::{clean_simple, pandoc};
+use crate::error::Error;
+use crate::{clean_simple, to_markdown};
 use scraper::{Html, Selector};
 
-pub fn get_hw_lat(parsed: &Html) -> Result<String, anyhow::Error> {
+pub fn get_hw_lat(parsed: &Html) -> Result<String, Error> {
     let selector_i = Selector::parse("hw i").unwrap();
-    let latin_text = match parsed.select(&selector_i).next() {
-        Some(latin) => pandoc(&latin.html())?,
-        None => "N/A".to_owned(),
-    };
+    let latin_text = parsed
+        .select(&selector_i)
+        .next()
+        .map_or_else(|| "N/A".to_owned(), |latin| to_markdown(&latin.html()));
     let cleaned = clean_hw_lat(&latin_text);
 
     Ok(cleaned)
@@ -100,9 +101,9 @@ mod tests {
         }
     }
 
-    // This will take a while to run; it has not yet run completely
-    // #[test]
-    fn _values_slow() {
+    // Regenerates every value from the raw HTML
+    #[test]
+    fn values_regen() {
         let conn = Connection::open("entries.sqlite").unwrap();
         let mut stmt = conn
             .prepare("SELECT id, raw_html, headword_latin FROM entries")
@@ -1,13 +1,16 @@
 use crate::clean_simple;
+use crate::error::Error;
 use scraper::{Html, Selector};
 
-#[must_use]
-pub fn get_hw_per(parsed: &Html) -> String {
+pub fn get_hw_per(parsed: &Html) -> Result<String, Error> {
     let selector_pa = Selector::parse("hw pa").unwrap();
-    let persian = parsed.select(&selector_pa).next().unwrap();
+    let persian = parsed
+        .select(&selector_pa)
+        .next()
+        .ok_or_else(|| Error::missing("hw pa"))?;
     let persian_text: String = persian.text().collect();
 
-    clean_hw_per(&persian_text)
+    Ok(clean_hw_per(&persian_text))
 }
 
 #[allow(clippy::let_and_return)]
@@ -34,8 +37,9 @@ fn clean_hw_per(input: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::abjad_values::load_abjad_prefs;
     use crate::charsets::ARABIC_ALLOWED;
-    use abjad::{Abjad, AbjadPrefs};
+    use abjad::Abjad;
     use rusqlite::Connection;
 
     #[test]
@@ -54,7 +58,7 @@ mod tests {
             })
             .unwrap();
 
-        let prefs = AbjadPrefs::default();
+        let prefs = load_abjad_prefs(&conn).unwrap();
 
         for entry in entry_iter {
             let (id, headword_persian, abjad_val) = entry.unwrap();
@@ -78,6 +82,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn missing() {
+        let parsed = Html::parse_fragment("<div><hw><i>āb</i></hw></div>");
+        assert!(matches!(
+            get_hw_per(&parsed),
+            Err(Error::MissingElement {
+                selector: "hw pa",
+                ..
+            })
+        ));
+    }
+
     #[test]
     fn chars() {
         let conn = Connection::open("entries.sqlite").unwrap();
@@ -129,7 +145,7 @@ mod tests {
         for entry in entry_iter {
             let (id, raw_html, headword_persian) = entry.unwrap();
             let parsed = Html::parse_fragment(&raw_html);
-            let persian_regen = get_hw_per(&parsed);
+            let persian_regen = get_hw_per(&parsed).unwrap();
 
             if headword_persian == "\u{0639}" {
                 continue;
@@ -0,0 +1,252 @@
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::error::{Error, Location};
+
+//
+// Type definitions
+//
+
+// Where a DB row belongs among the results on its page
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct Placement {
+    pub id: i64,
+    pub position: usize,
+    pub matched: bool, // False if placed by elimination, its HTML matching no result
+}
+
+//
+// Constants
+//
+
+const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+//
+// Public functions
+//
+
+// A permalink-safe ID such as "0014-002-5d3f0a9c": page, 1-based position among the results on
+// the page, and a hash of the headword, which catches entries shifting within a page
+#[must_use]
+pub fn entry_uid(page: u16, position: usize, headword_full: &str) -> String {
+    format!(
+        "{:04}-{:03}-{:08x}",
+        page,
+        position,
+        fold(fnv1a(headword_full.as_bytes()))
+    )
+}
+
+// Places each row of a page (ID and raw HTML, in ID order) at the 1-based index of the result
+// with the same HTML. The results should have any corrections applied. Rows that match no
+// result (e.g., fixed by hand, with no correction recorded) take the positions left over, in order;
+// placements are returned in the order of the rows
+pub fn page_positions(
+    page: u16,
+    rows: &[(i64, &str)],
+    results: &[String],
+) -> Result<Vec<Placement>, Error> {
+    if rows.len() != results.len() {
+        return Err(Error::Parse {
+            location: Location::page(page),
+            message: format!(
+                "{} entries on page, {} rows in DB",
+                results.len(),
+                rows.len()
+            ),
+        });
+    }
+
+    let mut positions: Vec<Option<usize>> = vec![None; rows.len()];
+    let mut taken = vec![false; results.len()];
+
+    for (i, html) in results.iter().enumerate() {
+        let found = rows
+            .iter()
+            .enumerate()
+            .position(|(r, (_, raw_html))| positions[r].is_none() && raw_html == html);
+
+        if let Some(r) = found {
+            positions[r] = Some(i + 1);
+            taken[i] = true;
+        }
+    }
+
+    let mut left = (1..=results.len()).filter(|&p| !taken[p - 1]);
+
+    let placements = rows
+        .iter()
+        .zip(positions)
+        .map(|((id, _), position)| Placement {
+            id: *id,
+            position: position.or_else(|| left.next()).unwrap_or_default(),
+            matched: position.is_some(),
+        })
+        .collect();
+
+    Ok(placements)
+}
+
+// Sets the position and uid of each row on a page from `page_positions`; leaves the transaction
+// to the caller
+pub fn assign_page_positions(
+    conn: &Connection,
+    page: u16,
+    results: &[String],
+) -> Result<Vec<Placement>, Error> {
+    let rows: Vec<(i64, String, String)> = conn
+        .prepare("SELECT id, raw_html, headword_full FROM entries WHERE page = ? ORDER BY id")?
+        .query_map([page], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let html: Vec<(i64, &str)> = rows.iter().map(|(id, h, _)| (*id, h.as_str())).collect();
+    let placements = page_positions(page, &html, results)?;
+
+    // Rows that swap places could otherwise collide on the unique uid index on the way
+    conn.execute(
+        "UPDATE entries SET uid = 'moving-' || id WHERE page = ?",
+        [page],
+    )?;
+
+    let mut stmt = conn.prepare("UPDATE entries SET position = ?1, uid = ?2 WHERE id = ?3")?;
+
+    for (placement, (id, _, headword_full)) in placements.iter().zip(&rows) {
+        stmt.execute((
+            placement.position,
+            entry_uid(page, placement.position, headword_full),
+            id,
+        ))?;
+    }
+
+    Ok(placements)
+}
+
+pub fn find_uid(conn: &Connection, uid: &str) -> Result<Option<i64>, Error> {
+    let mut stmt = conn.prepare("SELECT id FROM entries WHERE uid = ?")?;
+    let id = stmt.query_row([uid], |row| row.get(0)).optional()?;
+
+    Ok(id)
+}
+
+//
+// Private functions
+//
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[allow(clippy::cast_possible_truncation)]
+const fn fold(hash: u64) -> u32 {
+    (hash ^ (hash >> 32)) as u32
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ensure_table, insert_row, parse_entry};
+
+    #[test]
+    fn format() {
+        assert_eq!(fnv1a(b""), FNV_OFFSET);
+        assert_eq!(fnv1a(b"a"), 0xAF63_DC4C_8601_EC8C);
+
+        let uid = entry_uid(14, 2, "آتش‌آلود *ātish-ālūd*");
+        assert_eq!(uid.len(), 17);
+        assert!(uid.starts_with("0014-002-"));
+        assert_eq!(uid, entry_uid(14, 2, "آتش‌آلود *ātish-ālūd*"));
+        assert_ne!(uid, entry_uid(14, 2, "آتش *ātish*"));
+    }
+
+    #[test]
+    fn positions() {
+        let results: Vec<String> = ["<div>a</div>", "<div>b</div>", "<div>c</div>"]
+            .iter()
+            .map(|s| (*s).to_owned())
+            .collect();
+
+        // "b" was re-inserted by hand after the page was scraped; "c" was edited by hand
+        let rows = [
+            (1, "<div>a</div>"),
+            (2, "<div>c (fixed)</div>"),
+            (3, "<div>b</div>"),
+        ];
+        let placements = page_positions(5, &rows, &results).unwrap();
+
+        let found: Vec<(i64, usize, bool)> = placements
+            .iter()
+            .map(|p| (p.id, p.position, p.matched))
+            .collect();
+        assert_eq!(found, vec![(1, 1, true), (2, 3, false), (3, 2, true)]);
+
+        assert!(page_positions(5, &rows[..2], &results).is_err());
+    }
+
+    #[test]
+    fn backfill() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE entries (
+                id INTEGER NOT NULL PRIMARY KEY,
+                page INTEGER NOT NULL,
+                raw_html TEXT NOT NULL,
+                lang TEXT NOT NULL,
+                headword_full TEXT NOT NULL,
+                headword_persian TEXT NOT NULL,
+                headword_latin TEXT NOT NULL,
+                definitions TEXT NOT NULL
+            )",
+            [],
+        )
+        .unwrap();
+
+        for (page, headword) in [(3, "آب āb"), (3, "آبا ābā"), (4, "آباد ābād")] {
+            conn.execute(
+                "INSERT INTO entries (page, raw_html, lang, headword_full, headword_persian,
+                    headword_latin, definitions)
+                VALUES (?1, '', 'Persian', ?2, '', '', '')",
+                (page, headword),
+            )
+            .unwrap();
+        }
+
+        ensure_table(&conn).unwrap();
+
+        let id = find_uid(&conn, &entry_uid(3, 2, "آبا ābā")).unwrap();
+        assert_eq!(id, Some(2));
+        let id = find_uid(&conn, &entry_uid(4, 1, "آباد ābād")).unwrap();
+        assert_eq!(id, Some(3));
+
+        let entry = parse_entry(
+            4,
+            2,
+            "<div><hw><pa>آبار</pa> <i>ābār</i></hw>, Wells.</div>".to_owned(),
+        )
+        .unwrap();
+        assert_eq!(entry.uid, entry_uid(4, 2, &entry.headword_full));
+
+        let id = insert_row(&conn, entry.clone()).unwrap();
+        assert_eq!(find_uid(&conn, &entry.uid).unwrap(), Some(id));
+        assert!(insert_row(&conn, entry).is_err());
+
+        // The second entry on p. 3 was in fact added by hand, ahead of the first
+        conn.execute_batch(
+            "UPDATE entries SET raw_html = '<div>a</div>' WHERE id = 1;
+            UPDATE entries SET raw_html = '<div>b</div>' WHERE id = 2;",
+        )
+        .unwrap();
+        let results = vec!["<div>b</div>".to_owned(), "<div>a</div>".to_owned()];
+        assign_page_positions(&conn, 3, &results).unwrap();
+
+        let id = find_uid(&conn, &entry_uid(3, 1, "آبا ābā")).unwrap();
+        assert_eq!(id, Some(2));
+        let id = find_uid(&conn, &entry_uid(3, 2, "آب āb")).unwrap();
+        assert_eq!(id, Some(1));
+    }
+}
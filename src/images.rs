@@ -0,0 +1,220 @@
+use reqwest::Url;
+use reqwest::blocking::get;
+use rusqlite::{Connection, OptionalExtension};
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Location};
+use crate::{PREFIX, now};
+
+//
+// Type definitions
+//
+
+// An <img> found in an entry; `src` is resolved against the DSAL site
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Default, Serialize, Deserialize)]
+pub struct Image {
+    pub src: String,
+    pub alt: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+pub struct StoredImage {
+    pub url: String,
+    pub fetched_at: u64,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+//
+// Public functions
+//
+
+#[must_use]
+pub fn get_images(parsed: &Html) -> Vec<Image> {
+    let selector = Selector::parse("img").unwrap();
+
+    parsed
+        .select(&selector)
+        .filter_map(|img| {
+            let src = img.attr("src")?;
+
+            Some(Image {
+                src: resolve_url(src),
+                alt: img.attr("alt").unwrap_or_default().trim().to_owned(),
+            })
+        })
+        .collect()
+}
+
+// Replaces any images already recorded for the entry
+pub fn set_images(conn: &Connection, entry_id: i64, images: &[Image]) -> Result<(), Error> {
+    conn.execute("DELETE FROM images WHERE entry_id = ?", [entry_id])?;
+
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO images (entry_id, position, src, alt) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+
+    for (i, image) in images.iter().enumerate() {
+        stmt.execute((entry_id, i, &image.src, &image.alt))?;
+    }
+
+    Ok(())
+}
+
+pub fn load_images(conn: &Connection, entry_id: i64) -> Result<Vec<Image>, Error> {
+    let mut stmt =
+        conn.prepare_cached("SELECT src, alt FROM images WHERE entry_id = ? ORDER BY position")?;
+
+    let images = stmt
+        .query_map([entry_id], |row| {
+            Ok(Image {
+                src: row.get(0)?,
+                alt: row.get(1)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(images)
+}
+
+// Image files go in the page store, alongside the pages that reference them
+pub fn ensure_image_files_table(conn_pages: &Connection) -> Result<(), Error> {
+    conn_pages.execute(
+        "CREATE TABLE IF NOT EXISTS image_files (
+            url TEXT NOT NULL PRIMARY KEY,
+            fetched_at INTEGER NOT NULL,
+            status INTEGER NOT NULL,
+            content_type TEXT,
+            data BLOB NOT NULL
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+pub fn fetch_image(url: &str, page: u16) -> Result<StoredImage, Error> {
+    let at = |e: reqwest::Error| Error::from(e).at(Location::page(page));
+
+    let response = get(url).map_err(at)?;
+    let status = response.status().as_u16();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(ToOwned::to_owned);
+    let data = response.bytes().map_err(at)?.to_vec();
+
+    Ok(StoredImage {
+        url: url.to_owned(),
+        fetched_at: now(),
+        status,
+        content_type,
+        data,
+    })
+}
+
+pub fn store_image(conn_pages: &Connection, stored: &StoredImage) -> Result<(), Error> {
+    conn_pages.execute(
+        "INSERT OR REPLACE INTO image_files (url, fetched_at, status, content_type, data)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        (
+            &stored.url,
+            stored.fetched_at,
+            stored.status,
+            &stored.content_type,
+            &stored.data,
+        ),
+    )?;
+
+    Ok(())
+}
+
+pub fn load_image(conn_pages: &Connection, url: &str) -> Result<Option<StoredImage>, Error> {
+    let mut stmt = conn_pages
+        .prepare("SELECT fetched_at, status, content_type, data FROM image_files WHERE url = ?")?;
+
+    let stored = stmt
+        .query_row([url], |row| {
+            Ok(StoredImage {
+                url: url.to_owned(),
+                fetched_at: row.get(0)?,
+                status: row.get(1)?,
+                content_type: row.get(2)?,
+                data: row.get(3)?,
+            })
+        })
+        .optional()?;
+
+    Ok(stored)
+}
+
+//
+// Private functions
+//
+
+fn resolve_url(src: &str) -> String {
+    Url::parse(PREFIX)
+        .and_then(|base| base.join(src.trim()))
+        .map_or_else(|_| src.trim().to_owned(), Into::into)
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ensure_table, insert_row, parse_entry};
+
+    const ABJAD: &str = "<div><hw><pa>ابجد</pa> <i>abjad</i></hw>, The first of eight \
+        words <img src=\"/images/steingass/abjad.jpg\" alt=\"abjad table\"> (see the table)</div>";
+
+    #[test]
+    fn extraction() {
+        let parsed = Html::parse_fragment(ABJAD);
+        assert_eq!(
+            get_images(&parsed),
+            vec![Image {
+                src: "https://dsal.uchicago.edu/images/steingass/abjad.jpg".to_owned(),
+                alt: "abjad table".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn attachments() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_table(&conn).unwrap();
+
+        let entry = parse_entry(5, 1, ABJAD.to_owned()).unwrap();
+        assert_eq!(entry.images.len(), 1);
+        assert!(!entry.definitions.contains("abjad.jpg"));
+
+        let id = insert_row(&conn, entry.clone()).unwrap();
+        assert_eq!(load_images(&conn, id).unwrap(), entry.images);
+
+        set_images(&conn, id, &[]).unwrap();
+        assert_eq!(load_images(&conn, id).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn image_store() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_image_files_table(&conn).unwrap();
+
+        let stored = StoredImage {
+            url: "https://dsal.uchicago.edu/images/steingass/abjad.jpg".to_owned(),
+            fetched_at: 1_700_000_000,
+            status: 200,
+            content_type: Some("image/jpeg".to_owned()),
+            data: vec![0xFF, 0xD8, 0xFF],
+        };
+
+        store_image(&conn, &stored).unwrap();
+        assert_eq!(load_image(&conn, &stored.url).unwrap(), Some(stored));
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashSet;
+
+use rusqlite::Connection;
+
+use crate::Entry;
+use crate::error::Error;
+use crate::normalize::normalize_for_search;
+use crate::query::{Query, Script, Search, search};
+
+//
+// Type definitions
+//
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub enum AffixPosition {
+    Prefix,
+    Suffix,
+}
+
+// One way of undoing an inflection: `form` is removed and `replacement` put in its place
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct Affix {
+    pub label: &'static str,
+    pub position: AffixPosition,
+    pub form: &'static str,
+    pub replacement: &'static str,
+}
+
+// A possible base form of a word, with the affixes stripped to reach it, outermost first
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Default)]
+pub struct Analysis {
+    pub lemma: String,
+    pub affixes: Vec<Affix>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Default)]
+pub struct InflectedMatch {
+    pub analysis: Analysis,
+    pub entries: Vec<(i64, Entry)>,
+}
+
+//
+// Constants
+//
+
+// At most this many affixes are stripped from a word
+pub const MAX_AFFIXES: usize = 3;
+
+// Tried in this order, so longer forms come before the shorter ones they end with; forms are
+// normalized as by `normalize_for_search`. Verbs are traced back to the infinitive through the
+// past stem only, since present stems (e.g., "رو" for "رفتن") are irregular
+pub const AFFIXES: [Affix; 25] = [
+    suffix("object marker rā", "را", ""),
+    suffix("superlative -tarīn", "ترین", ""),
+    suffix("comparative -tar", "تر", ""),
+    suffix("plural -hā", "ها", ""),
+    suffix("plural -gān", "گان", "ه"),
+    suffix("plural -yān", "یان", ""),
+    suffix("plural -ān", "ان", ""),
+    suffix("possessive -šān", "شان", ""),
+    suffix("possessive -tān", "تان", ""),
+    suffix("possessive -mān", "مان", ""),
+    suffix("possessive -am", "ام", ""),
+    suffix("possessive -aš", "اش", ""),
+    suffix("possessive -am", "م", ""),
+    suffix("possessive -at", "ت", ""),
+    suffix("possessive -aš", "ش", ""),
+    suffix("ezāfe or indefinite -ī", "ی", ""),
+    suffix("past 1pl -īm", "یم", "ن"),
+    suffix("past 2pl -īd", "ید", "ن"),
+    suffix("past 3pl -and", "ند", "ن"),
+    suffix("past 1sg -am", "م", "ن"),
+    suffix("past 2sg -ī", "ی", "ن"),
+    suffix("past stem", "ت", "تن"),
+    suffix("past stem", "د", "دن"),
+    prefix("negative continuous nemī-", "نمی"),
+    prefix("continuous mī-", "می"),
+];
+
+// Shortest base form to try
+const MIN_LEMMA_CHARS: usize = 2;
+
+const ZWNJ: char = '\u{200C}';
+const HEH: char = '\u{0647}';
+const YEH: &str = "\u{06CC}";
+const HAMZA_ABOVE: char = '\u{0654}';
+
+//
+// Public functions
+//
+
+// Candidate base forms of a word from running text, the word itself first, then by number of
+// affixes stripped and the order of `AFFIXES`
+#[must_use]
+pub fn analyses(word: &str) -> Vec<Analysis> {
+    let normalized = normalize_for_search(word);
+
+    if normalized.is_empty() {
+        return Vec::new();
+    }
+
+    let mut seen = HashSet::from([normalized.clone()]);
+    let mut found = vec![Analysis {
+        lemma: normalized,
+        affixes: Vec::new(),
+    }];
+    let mut level_start = 0;
+
+    for _ in 0..MAX_AFFIXES {
+        let level_end = found.len();
+
+        for i in level_start..level_end {
+            for affix in AFFIXES {
+                let Some(lemma) = strip(&found[i].lemma, affix) else {
+                    continue;
+                };
+
+                if seen.insert(lemma.clone()) {
+                    let mut affixes = found[i].affixes.clone();
+                    affixes.push(affix);
+                    found.push(Analysis { lemma, affixes });
+                }
+            }
+        }
+
+        level_start = level_end;
+    }
+
+    found
+}
+
+// Every analysis of the word whose base form is a headword, in the order of `analyses`
+pub fn lookup_inflected(conn: &Connection, word: &str) -> Result<Vec<InflectedMatch>, Error> {
+    let mut matches = Vec::new();
+
+    for analysis in analyses(word) {
+        let entries = search(
+            conn,
+            &Search::new(Query::Headword {
+                script: Script::Persian,
+                text: analysis.lemma.clone(),
+                prefix: false,
+            }),
+        )?;
+
+        if !entries.is_empty() {
+            matches.push(InflectedMatch { analysis, entries });
+        }
+    }
+
+    Ok(matches)
+}
+
+// Forms of a headword as it may appear in running text, for readers that look words up by
+// their inflections: the plural in -hā (and -gān after a silent h), and the ezāfe and
+// indefinite endings. The plural in -ān is otherwise left out, as too many words do not take
+// it; only single words in Arabic script are inflected
+#[must_use]
+pub fn inflected_forms(headword_persian: &str) -> Vec<String> {
+    let word = headword_persian.trim();
+    let arabic_script = word
+        .chars()
+        .all(|c| ('\u{0600}'..='\u{06FF}').contains(&c) || c == ZWNJ);
+
+    if !arabic_script || word.chars().count() < MIN_LEMMA_CHARS {
+        return Vec::new();
+    }
+
+    let mut forms = Vec::new();
+
+    for joiner in [String::from(ZWNJ), String::new()] {
+        forms.push(format!("{}{}ها", word, joiner));
+        forms.push(format!("{}{}های", word, joiner));
+    }
+
+    if let Some(stem) = word.strip_suffix(HEH) {
+        // Silent h, e.g., "بنده", "بندگان", "بندهٔ", "بنده‌ای"
+        forms.push(format!("{}گان", stem));
+        forms.push(format!("{}{}", word, HAMZA_ABOVE));
+        forms.push(format!("{}{}{}", word, ZWNJ, YEH));
+        forms.push(format!("{}{}ای", word, ZWNJ));
+        forms.push(format!("{}ای", word));
+    } else if word.ends_with(['\u{0627}', '\u{0648}']) {
+        // Long vowels take a glide, e.g., "دانا", "دانای", "دانایی"
+        forms.push(format!("{}{}", word, YEH));
+        forms.push(format!("{}{}{}", word, YEH, YEH));
+    } else {
+        // The ezāfe of a final consonant is not written
+        forms.push(format!("{}{}", word, YEH));
+    }
+
+    let mut seen = HashSet::new();
+    forms.retain(|form| form != word && seen.insert(form.clone()));
+    forms
+}
+
+//
+// Private functions
+//
+
+const fn suffix(label: &'static str, form: &'static str, replacement: &'static str) -> Affix {
+    Affix {
+        label,
+        position: AffixPosition::Suffix,
+        form,
+        replacement,
+    }
+}
+
+const fn prefix(label: &'static str, form: &'static str) -> Affix {
+    Affix {
+        label,
+        position: AffixPosition::Prefix,
+        form,
+        replacement: "",
+    }
+}
+
+// Affixes may be joined to the word or set off by a (normalized) ZWNJ
+fn strip(word: &str, affix: Affix) -> Option<String> {
+    let lemma = match affix.position {
+        AffixPosition::Suffix => {
+            let stem = word.strip_suffix(affix.form)?.trim_end();
+            format!("{}{}", stem, affix.replacement)
+        }
+        AffixPosition::Prefix => {
+            let stem = word.strip_prefix(affix.form)?.trim_start();
+            format!("{}{}", affix.replacement, stem)
+        }
+    };
+
+    (lemma.chars().count() >= MIN_LEMMA_CHARS).then_some(lemma)
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    fn labels(word: &str, lemma: &str) -> Option<Vec<&'static str>> {
+        analyses(word)
+            .into_iter()
+            .find(|a| a.lemma == lemma)
+            .map(|a| a.affixes.iter().map(|affix| affix.label).collect())
+    }
+
+    #[test]
+    fn stripping() {
+        assert_eq!(labels("کتاب‌ها", "کتاب").unwrap(), vec!["plural -hā"]);
+        assert_eq!(
+            labels("کتاب‌هایم", "کتاب").unwrap(),
+            vec!["possessive -am", "ezāfe or indefinite -ī", "plural -hā"]
+        );
+        assert_eq!(labels("بندگان", "بنده").unwrap(), vec!["plural -gān"]);
+        assert_eq!(labels("خانه‌ام", "خانه").unwrap(), vec!["possessive -am"]);
+        assert_eq!(
+            labels("بزرگ‌ترین", "بزرگ").unwrap(),
+            vec!["superlative -tarīn"]
+        );
+        assert_eq!(
+            labels("می‌رفتند", "رفتن").unwrap(),
+            vec!["past 3pl -and", "continuous mī-"]
+        );
+        assert_eq!(
+            labels("نمی‌رفت", "رفتن").unwrap(),
+            vec!["past stem", "negative continuous nemī-"]
+        );
+
+        assert_eq!(analyses("کتاب")[0].affixes, Vec::new());
+        assert!(analyses("").is_empty());
+    }
+
+    #[test]
+    fn forms() {
+        let forms = inflected_forms("کتاب");
+        assert_eq!(
+            forms,
+            vec!["کتاب‌ها", "کتاب‌های", "کتابها", "کتابهای", "کتابی"]
+        );
+
+        let forms = inflected_forms("بنده");
+        assert!(forms.contains(&"بندگان".to_owned()));
+        assert!(forms.contains(&"بندهٔ".to_owned()));
+        assert!(forms.contains(&"بنده‌ای".to_owned()));
+
+        assert!(inflected_forms("دانا").contains(&"دانایی".to_owned()));
+
+        // Every plural leads back to its headword
+        for form in forms.iter().filter(|f| f.contains("ها")) {
+            assert!(analyses(form).iter().any(|a| a.lemma == "بنده"), "{}", form);
+        }
+
+        assert!(inflected_forms("آب رو").is_empty());
+        assert!(inflected_forms("āb").is_empty());
+    }
+
+    #[test]
+    fn lookup() {
+        let conn = test_db(&[
+            (
+                1020,
+                "<div><hw><pa>کتاب</pa> <i>kitāb</i></hw>, A book</div>",
+            ),
+            (
+                1020,
+                "<div><hw><pa>رفتن</pa> <i>raftan</i></hw>, To go</div>",
+            ),
+        ]);
+
+        let matches = lookup_inflected(&conn, "كتاب‌هایش").unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].analysis.lemma, "کتاب");
+        assert_eq!(matches[0].analysis.affixes.len(), 3);
+        assert_eq!(matches[0].entries[0].0, 1);
+
+        let matches = lookup_inflected(&conn, "می‌رفتیم").unwrap();
+        assert_eq!(matches[0].entries[0].1.headword_persian, "رفتن");
+
+        assert!(lookup_inflected(&conn, "دریا").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,423 @@
+use std::io::{BufRead, Read, Write};
+use std::str::FromStr;
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::abjad_values::{load_abjad_prefs, parse_prefs, prefs_to_string, record_abjad_prefs};
+use crate::error::{Error, Location};
+use crate::images::Image;
+use crate::langs::Lang;
+use crate::normalize::{fold_latin, normalize_for_search};
+use crate::roots::entry_root;
+use crate::senses::Sense;
+use crate::xrefs::resolve_cross_refs;
+use crate::{Entry, for_each_entry, insert_row_with_id};
+
+//
+// Type definitions
+//
+
+// One entry as exported; the search columns and root are derived again on import
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct JsonEntry {
+    pub id: i64,
+    pub uid: String,
+    pub page: u16,
+    pub position: usize,
+    pub lang: JsonLang,
+    pub headword_full: String,
+    pub headword_persian: String,
+    pub headword_latin: String,
+    pub definitions: String,
+    pub abjad: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_html: Option<String>,
+    #[serde(default)]
+    pub images: Vec<Image>,
+    #[serde(default)]
+    pub senses: Vec<Sense>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct JsonLang {
+    pub code: Option<String>, // None for unmarked entries
+    pub label: String,
+}
+
+// How the DB was built, so that an import reproduces it; see the metadata table
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct JsonMetadata {
+    pub abjad_prefs: String, // See `abjad_values::prefs_to_string`
+}
+
+// The first line of a JSON Lines export
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct JsonHeader {
+    pub metadata: JsonMetadata,
+}
+
+// A whole JSON export
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct JsonExport {
+    pub metadata: JsonMetadata,
+    pub entries: Vec<JsonEntry>,
+}
+
+//
+// Implementations
+//
+
+impl JsonEntry {
+    #[must_use]
+    pub fn from_entry(id: i64, entry: Entry, raw_html: bool) -> Self {
+        Self {
+            id,
+            uid: entry.uid,
+            page: entry.page,
+            position: entry.position,
+            lang: JsonLang {
+                code: entry.lang.code().map(str::to_owned),
+                label: entry.lang.as_str().to_owned(),
+            },
+            headword_full: entry.headword_full,
+            headword_persian: entry.headword_persian,
+            headword_latin: entry.headword_latin,
+            definitions: entry.definitions,
+            abjad: entry.abjad,
+            raw_html: raw_html.then_some(entry.raw_html),
+            images: entry.images,
+            senses: entry.senses,
+        }
+    }
+
+    // An entry exported without its raw HTML cannot be re-parsed or verified, so is refused
+    pub fn into_entry(self) -> Result<(i64, Entry), Error> {
+        let location = Location::entry(self.page, self.position);
+
+        let lang = Lang::from_str(&self.lang.label).map_err(|e| Error::Parse {
+            location,
+            message: e.to_string(),
+        })?;
+
+        let raw_html = self.raw_html.ok_or_else(|| Error::Parse {
+            location,
+            message: "No raw HTML; only exports that include it can be imported".to_owned(),
+        })?;
+
+        let entry = Entry {
+            page: self.page,
+            position: self.position,
+            uid: self.uid,
+            raw_html,
+            lang,
+            headword_search: normalize_for_search(&self.headword_persian),
+            headword_latin_search: fold_latin(&self.headword_latin),
+            root: entry_root(lang, &self.headword_persian),
+            headword_full: self.headword_full,
+            headword_persian: self.headword_persian,
+            headword_latin: self.headword_latin,
+            definitions: self.definitions,
+            abjad: self.abjad,
+            images: self.images,
+            senses: self.senses,
+        };
+
+        Ok((self.id, entry))
+    }
+}
+
+impl JsonMetadata {
+    pub fn from_db(conn: &Connection) -> Result<Self, Error> {
+        Ok(Self {
+            abjad_prefs: prefs_to_string(load_abjad_prefs(conn)?),
+        })
+    }
+
+    // Leaves the transaction to the caller
+    pub fn record(&self, conn: &Connection) -> Result<(), Error> {
+        record_abjad_prefs(conn, parse_prefs(&self.abjad_prefs)?)
+    }
+}
+
+//
+// Public functions
+//
+
+// A metadata line, then one JSON object per entry, in ID order
+pub fn write_jsonl<W: Write>(
+    conn: &Connection,
+    out: &mut W,
+    raw_html: bool,
+) -> Result<usize, Error> {
+    let header = JsonHeader {
+        metadata: JsonMetadata::from_db(conn)?,
+    };
+    serde_json::to_writer(&mut *out, &header).map_err(|e| json_error(&e))?;
+    writeln!(out)?;
+
+    let mut count = 0;
+
+    for_each_entry(conn, |id, entry| {
+        serde_json::to_writer(&mut *out, &JsonEntry::from_entry(id, entry, raw_html))
+            .map_err(|e| json_error(&e))?;
+        writeln!(out)?;
+
+        count += 1;
+        Ok(())
+    })?;
+
+    Ok(count)
+}
+
+// A single object, with the entries still written one at a time
+pub fn write_json<W: Write>(
+    conn: &Connection,
+    out: &mut W,
+    raw_html: bool,
+) -> Result<usize, Error> {
+    let metadata = JsonMetadata::from_db(conn)?;
+    let mut count = 0;
+
+    write!(out, "{{\n  \"metadata\": ")?;
+    serde_json::to_writer(&mut *out, &metadata).map_err(|e| json_error(&e))?;
+    write!(out, ",\n  \"entries\": [")?;
+
+    for_each_entry(conn, |id, entry| {
+        write!(out, "{}\n    ", if count == 0 { "" } else { "," })?;
+        serde_json::to_writer(&mut *out, &JsonEntry::from_entry(id, entry, raw_html))
+            .map_err(|e| json_error(&e))?;
+
+        count += 1;
+        Ok(())
+    })?;
+
+    writeln!(out, "{}]\n}}", if count == 0 { "" } else { "\n  " })?;
+
+    Ok(count)
+}
+
+// Reads entries line by line into an empty DB, keeping their IDs and the metadata; the
+// cross-references are resolved once all of the entries are in
+pub fn import_jsonl<R: BufRead>(conn: &Connection, input: R) -> Result<usize, Error> {
+    let tx = conn.unchecked_transaction()?;
+    ensure_empty(&tx)?;
+
+    let mut has_header = false;
+    let mut count = 0;
+
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let invalid = |e: serde_json::Error| Error::Parse {
+            location: Location::default(),
+            message: format!("Line {}: {}", i + 1, e),
+        };
+
+        if !has_header {
+            let header: JsonHeader = serde_json::from_str(&line).map_err(invalid)?;
+            header.metadata.record(&tx)?;
+            has_header = true;
+            continue;
+        }
+
+        let json: JsonEntry = serde_json::from_str(&line).map_err(invalid)?;
+
+        let (id, entry) = json.into_entry()?;
+        insert_row_with_id(&tx, Some(id), entry)?;
+        count += 1;
+    }
+
+    if !has_header {
+        return Err(Error::Parse {
+            location: Location::default(),
+            message: "Empty export; expected a metadata line".to_owned(),
+        });
+    }
+
+    tx.commit()?;
+    resolve_cross_refs(conn)?;
+
+    Ok(count)
+}
+
+// As `import_jsonl`, for an export written by `write_json`
+pub fn import_json<R: Read>(conn: &Connection, input: R) -> Result<usize, Error> {
+    let export: JsonExport = serde_json::from_reader(input).map_err(|e| json_error(&e))?;
+
+    let tx = conn.unchecked_transaction()?;
+    ensure_empty(&tx)?;
+    export.metadata.record(&tx)?;
+
+    let count = export.entries.len();
+
+    for json in export.entries {
+        let (id, entry) = json.into_entry()?;
+        insert_row_with_id(&tx, Some(id), entry)?;
+    }
+
+    tx.commit()?;
+    resolve_cross_refs(conn)?;
+
+    Ok(count)
+}
+
+//
+// Private functions
+//
+
+fn json_error(e: &serde_json::Error) -> Error {
+    Error::Parse {
+        location: Location::default(),
+        message: format!("Invalid JSON: {}", e),
+    }
+}
+
+fn ensure_empty(conn: &Connection) -> Result<(), Error> {
+    let existing: usize = conn.query_row("SELECT COUNT(*) FROM entries", [], |row| row.get(0))?;
+
+    if existing > 0 {
+        return Err(Error::Parse {
+            location: Location::default(),
+            message: format!(
+                "Cannot import into a DB that already has {} entries",
+                existing
+            ),
+        });
+    }
+
+    Ok(())
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use abjad::{AbjadPrefs, LetterOrder};
+
+    use super::*;
+    use crate::abjad_values::recompute_abjad;
+    use crate::{ensure_table, test_db};
+
+    fn setup() -> Connection {
+        let conn = test_db(&[
+            (
+                3,
+                "<div><lang>a</lang> <hw><pa>آب</pa> <i>āb</i></hw>, s. Water; آب رو <i>āb-rū</i>, Honour</div>",
+            ),
+            (3, "<div><hw><pa>کتاب</pa> <i>kitāb</i></hw>, A book</div>"),
+            (
+                3,
+                "<div><lang>A</lang> <hw><pa>مکتوب</pa> <i>maktūb</i></hw>, Written; see آب</div>",
+            ),
+        ]);
+
+        // A gap in the IDs, which the import should keep
+        conn.execute("DELETE FROM entries WHERE id = 2", [])
+            .unwrap();
+
+        conn
+    }
+
+    fn cross_refs(conn: &Connection) -> Vec<(i64, Option<i64>, String)> {
+        conn.prepare("SELECT entry_id, target_id, status FROM cross_refs ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn all_entries(conn: &Connection) -> Vec<(i64, Entry)> {
+        let mut entries = Vec::new();
+        for_each_entry(conn, |id, entry| {
+            entries.push((id, entry));
+            Ok(())
+        })
+        .unwrap();
+        entries
+    }
+
+    #[test]
+    fn jsonl_round_trip() {
+        let conn = setup();
+
+        // Values under other preferences must survive the round trip, along with the preferences
+        let prefs = AbjadPrefs {
+            letter_order: LetterOrder::Maghribi,
+            ..AbjadPrefs::default()
+        };
+        recompute_abjad(&conn, prefs).unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(write_jsonl(&conn, &mut out, true).unwrap(), 2);
+
+        let text = String::from_utf8(out.clone()).unwrap();
+        let mut lines = text.lines();
+        let header: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(header["metadata"]["abjad_prefs"], prefs_to_string(prefs));
+
+        let first: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(first["lang"]["code"], "a");
+        assert_eq!(first["lang"]["label"], "Arabic & Persian");
+        assert_eq!(first["abjad"], 3);
+        assert_eq!(first["senses"][1]["phrases"][0]["latin"], "āb-rū");
+
+        let imported = Connection::open_in_memory().unwrap();
+        ensure_table(&imported).unwrap();
+        assert_eq!(import_jsonl(&imported, out.as_slice()).unwrap(), 2);
+
+        assert_eq!(all_entries(&imported), all_entries(&conn));
+        assert_eq!(load_abjad_prefs(&imported).unwrap(), prefs);
+        assert_eq!(
+            cross_refs(&imported),
+            vec![(3, Some(1), "resolved".to_owned())]
+        );
+
+        // Importing twice would duplicate the entries
+        assert!(import_jsonl(&imported, out.as_slice()).is_err());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let conn = setup();
+
+        let mut out = Vec::new();
+        assert_eq!(write_json(&conn, &mut out, false).unwrap(), 2);
+
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let entries = &value["entries"];
+        assert_eq!(entries[1]["id"], 3);
+        assert_eq!(entries[1]["lang"]["code"], "A");
+        assert!(entries[1].get("raw_html").is_none());
+
+        // Without the raw HTML, the DB could not be rebuilt
+        let imported = Connection::open_in_memory().unwrap();
+        ensure_table(&imported).unwrap();
+        assert!(import_json(&imported, out.as_slice()).is_err());
+
+        let mut out = Vec::new();
+        write_json(&conn, &mut out, true).unwrap();
+        assert_eq!(import_json(&imported, out.as_slice()).unwrap(), 2);
+
+        let (id, entry) = all_entries(&imported).pop().unwrap();
+        assert_eq!(id, 3);
+        assert_eq!(entry.root.as_deref(), Some("ک-ت-ب"));
+        assert_eq!(all_entries(&imported), all_entries(&conn));
+        assert_eq!(cross_refs(&imported).len(), 1);
+
+        // An empty DB makes an empty list
+        let empty = Connection::open_in_memory().unwrap();
+        ensure_table(&empty).unwrap();
+        let mut out = Vec::new();
+        write_json(&empty, &mut out, false).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value["entries"], serde_json::json!([]));
+    }
+}
@@ -1,6 +1,9 @@
 use scraper::{Html, Selector};
+use std::fmt;
 use std::str::FromStr;
 
+use crate::error::{Error, Location};
+
 //
 // Type definitions
 //
@@ -41,10 +44,65 @@ pub enum Lang {
     PersianArabicTurkish, // a t, t a, a p t
 }
 
-#[derive(Debug)]
-pub struct LangParseError;
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LangParseError(pub String);
 
 impl Lang {
+    // Whether Steingass marks the word as (at least in part) Arabic
+    #[must_use]
+    pub const fn includes_arabic(self) -> bool {
+        matches!(
+            self,
+            Self::Arabic
+                | Self::ArabicGreek
+                | Self::ArabicTurkish
+                | Self::PersianArabic
+                | Self::PersianArabicGreek
+                | Self::PersianArabicHindi
+                | Self::PersianArabicTurkish
+        )
+    }
+
+    // The canonical tag in the source; typos and variants (e.g., "B", "A P") map to one tag
+    #[must_use]
+    pub const fn code(self) -> Option<&'static str> {
+        let code = match self {
+            Self::Unmarked => return None,
+
+            Self::Arabic => "A",
+            Self::English => "E",
+            Self::Greek => "G",
+            Self::Hebrew => "HE",
+            Self::Hindi => "H",
+            Self::Latin => "L",
+            Self::Mongolian => "M",
+            Self::Persian => "P",
+            Self::Portuguese => "PORT",
+            Self::Russian => "R",
+            Self::Sanskrit => "S",
+            Self::Spanish => "SP",
+            Self::Syriac => "SY",
+            Self::Turkish => "T",
+            Self::Urdu => "U",
+
+            Self::ArabicGreek => "A G",
+            Self::ArabicTurkish => "A T",
+
+            Self::PersianArabic => "a",
+            Self::PersianGreek => "g",
+            Self::PersianHindi => "h",
+            Self::PersianMongolian => "m",
+            Self::PersianRussian => "r",
+            Self::PersianTurkish => "t",
+
+            Self::PersianArabicGreek => "g a",
+            Self::PersianArabicHindi => "a h",
+            Self::PersianArabicTurkish => "a t",
+        };
+
+        Some(code)
+    }
+
     #[must_use]
     pub const fn as_str(self) -> &'static str {
         match self {
@@ -83,11 +141,21 @@ impl Lang {
     }
 }
 
+impl fmt::Display for LangParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unrecognized language label: {}", self.0)
+    }
+}
+
+impl std::error::Error for LangParseError {}
+
 impl FromStr for Lang {
     type Err = LangParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let parsed = match s {
+            "Unmarked (i.e., Persian)" => Self::Unmarked,
+
             "Arabic" => Self::Arabic,
             "English" => Self::English,
             "Greek" => Self::Greek,
@@ -118,7 +186,7 @@ impl FromStr for Lang {
             "Arabic & Hindi & Persian" => Self::PersianArabicHindi,
             "Arabic & Persian & Turkish" => Self::PersianArabicTurkish,
 
-            _ => Self::Unmarked,
+            _ => return Err(LangParseError(s.to_owned())),
         };
 
         Ok(parsed)
@@ -129,8 +197,8 @@ impl FromStr for Lang {
 // Functions
 //
 
-#[must_use]
-pub fn get_lang(parsed: &Html) -> Lang {
+#[allow(clippy::useless_let_if_seq)]
+pub fn get_lang(parsed: &Html) -> Result<Lang, Error> {
     let selector = Selector::parse("lang").unwrap();
     let mut lang = Lang::Unmarked;
 
@@ -171,11 +239,16 @@ pub fn get_lang(parsed: &Html) -> Lang {
             "a h" => Lang::PersianArabicHindi,
             "a t" | "t a" | "a p t" => Lang::PersianArabicTurkish,
 
-            _ => panic!("Unrecognized language: {}", trimmed),
+            _ => {
+                return Err(Error::UnknownLanguage {
+                    location: Location::default(),
+                    tag: trimmed.to_owned(),
+                });
+            }
         };
     }
 
-    lang
+    Ok(lang)
 }
 
 #[cfg(test)]
@@ -212,7 +285,7 @@ mod tests {
             );
 
             let parsed = Html::parse_fragment(&raw_html);
-            let lang_regen = get_lang(&parsed);
+            let lang_regen = get_lang(&parsed).unwrap();
             assert_eq!(
                 lang_regen, lang_from_str,
                 "Lang re-parsing failure, p. {}",
@@ -221,6 +294,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn unrecognized() {
+        let parsed = Html::parse_fragment("<div><hw>x</hw> <lang>Q.</lang></div>");
+        assert!(matches!(
+            get_lang(&parsed),
+            Err(Error::UnknownLanguage { tag, .. }) if tag == "Q"
+        ));
+
+        assert_eq!(
+            Lang::from_str("Klingon"),
+            Err(LangParseError("Klingon".to_owned()))
+        );
+        assert_eq!(Lang::from_str(Lang::Unmarked.as_str()), Ok(Lang::Unmarked));
+    }
+
     #[test]
     fn variants() {
         let variants = variant_count::<Lang>();
@@ -7,23 +7,64 @@
 )]
 #![feature(variant_count)]
 
-use core::str;
-use std::io::Write;
-use std::process::Command;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use reqwest::blocking::get;
-use rusqlite::Connection;
+use abjad::AbjadPrefs;
+use rusqlite::types::Type;
+use rusqlite::{Connection, Row};
 use scraper::{ElementRef, Html, Selector};
-use tempfile::NamedTempFile;
 
+pub mod abjad_values;
+pub mod anki;
+pub mod articles;
 pub mod charsets;
+pub mod corrections;
 pub mod defs;
+pub mod dictd;
+pub mod dictzip;
+pub mod epub;
+pub mod error;
+pub mod export;
+pub mod fts;
+pub mod fuzzy;
 pub mod hw_full;
 pub mod hw_lat;
 pub mod hw_per;
+pub mod ids;
+pub mod images;
+pub mod inflection;
+pub mod json;
 pub mod langs;
-
-use langs::Lang;
+pub mod markdown;
+pub mod metadata;
+pub mod migrations;
+pub mod normalize;
+pub mod pages;
+pub mod phrases;
+pub mod progress;
+pub mod query;
+pub mod roots;
+pub mod senses;
+pub mod stardict;
+pub mod tei;
+pub mod xdxf;
+pub mod xml;
+pub mod xrefs;
+
+use abjad_values::abjad_value;
+use defs::except_headword;
+use error::{Error, Location};
+use hw_full::select_full_headword;
+use hw_lat::get_hw_lat;
+use hw_per::get_hw_per;
+use ids::entry_uid;
+use images::{Image, get_images, load_images, set_images};
+use langs::{Lang, get_lang};
+use migrations::migrate;
+use normalize::{fold_latin, normalize_for_search};
+use roots::entry_root;
+use senses::{Sense, load_senses, parse_senses, set_senses};
 
 //
 // Types
@@ -32,12 +73,20 @@ use langs::Lang;
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Default)]
 pub struct Entry {
     pub page: u16,
+    pub position: usize, // 1-based, among the results on the page
+    pub uid: String,     // See `ids::entry_uid`
     pub raw_html: String,
     pub lang: Lang,
     pub headword_full: String,
     pub headword_persian: String,
     pub headword_latin: String,
     pub definitions: String,
+    pub abjad: u32, // Of `headword_persian`, under the preferences recorded in the DB
+    pub headword_search: String, // `headword_persian`, normalized for search
+    pub headword_latin_search: String, // `headword_latin`, folded to ASCII
+    pub root: Option<String>, // For words marked as Arabic; see `roots::candidate_roots`
+    pub images: Vec<Image>,
+    pub senses: Vec<Sense>,
 }
 
 //
@@ -50,48 +99,64 @@ pub const BAD_PAGES: [u16; 6] = [2, 41, 486, 520, 665, 666];
 
 const PREFIX: &str = "https://dsal.uchicago.edu/cgi-bin/app/steingass_query.py?page=";
 
+// In the order expected by `read_entry`
+const ENTRY_COLUMNS: &str = "id, page, position, uid, raw_html, lang, headword_full, \
+    headword_persian, headword_latin, definitions, abjad, headword_search, \
+    headword_latin_search, root";
+
 //
-// Public functions
+// Implementations
 //
 
-pub fn ensure_table(conn: &Connection) -> Result<(), anyhow::Error> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS entries (
-            id INTEGER NOT NULL PRIMARY KEY,
-            page INTEGER NOT NULL,
-            raw_html TEXT NOT NULL,
-            lang TEXT NOT NULL,
-            headword_full TEXT NOT NULL,
-            headword_persian TEXT NOT NULL,
-            headword_latin TEXT NOT NULL,
-            definitions TEXT NOT NULL
-        )",
-        [],
-    )?;
+impl Entry {
+    // Recomputes every column derived from the page, position, headwords and definitions; the
+    // one place these are set, so that a correction to any of them carries through. Callers that
+    // correct an entry keep its uid (see `Corrections::parse_entry`)
+    pub fn derive_fields(&mut self, prefs: AbjadPrefs) {
+        self.uid = entry_uid(self.page, self.position, &self.headword_full);
+        self.abjad = abjad_value(&self.headword_persian, prefs);
+        self.headword_search = normalize_for_search(&self.headword_persian);
+        self.headword_latin_search = fold_latin(&self.headword_latin);
+        self.root = entry_root(self.lang, &self.headword_persian);
+        self.senses = parse_senses(&self.definitions);
+    }
+}
 
+//
+// Public functions
+//
+
+// Creates the schema, or brings an existing DB up to date with it
+pub fn ensure_table(conn: &Connection) -> Result<(), Error> {
+    migrate(conn)?;
     Ok(())
 }
 
-pub fn fetch_html(page: u16) -> Result<Html, anyhow::Error> {
-    let url = format!("{}{}", PREFIX, page);
-    let response_text = get(url)?.text()?;
-    let parsed = Html::parse_document(&response_text);
+pub fn fetch_html(page: u16) -> Result<Html, Error> {
+    let fetched = pages::fetch_page(page)?;
+    let parsed = Html::parse_document(&fetched.html);
     Ok(parsed)
 }
 
 #[must_use]
-pub fn select_results(parsed: &Html) -> Vec<ElementRef> {
+pub fn select_results(parsed: &Html) -> Vec<ElementRef<'_>> {
     let selector = Selector::parse("#results_display .container div").unwrap();
     parsed.select(&selector).collect()
 }
 
-pub fn count_page_entries(conn: &Connection, page: u16) -> Result<usize, anyhow::Error> {
+pub fn count_page_entries(conn: &Connection, page: u16) -> Result<usize, Error> {
     let mut stmt = conn.prepare("SELECT COUNT(*) FROM entries WHERE page = ?")?;
     let count: usize = stmt.query_row([page], |row| row.get(0))?;
     Ok(count)
 }
 
-pub fn insert_row(conn: &Connection, entry: Entry) -> Result<(), anyhow::Error> {
+// Returns the ID of the new row
+pub fn insert_row(conn: &Connection, entry: Entry) -> Result<i64, Error> {
+    insert_row_with_id(conn, None, entry)
+}
+
+// As `insert_row`, but keeping a given ID (e.g., when importing an export)
+pub fn insert_row_with_id(conn: &Connection, id: Option<i64>, entry: Entry) -> Result<i64, Error> {
     conn.execute(
         "INSERT INTO entries (
             page,
@@ -100,8 +165,15 @@ pub fn insert_row(conn: &Connection, entry: Entry) -> Result<(), anyhow::Error>
             headword_full,
             headword_persian,
             headword_latin,
-            definitions
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            definitions,
+            position,
+            uid,
+            abjad,
+            headword_search,
+            headword_latin_search,
+            root,
+            id
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
         (
             entry.page,
             entry.raw_html,
@@ -110,16 +182,202 @@ pub fn insert_row(conn: &Connection, entry: Entry) -> Result<(), anyhow::Error>
             entry.headword_persian,
             entry.headword_latin,
             entry.definitions,
+            entry.position,
+            entry.uid,
+            entry.abjad,
+            entry.headword_search,
+            entry.headword_latin_search,
+            entry.root,
+            id,
+        ),
+    )?;
+
+    let id = conn.last_insert_rowid();
+    set_images(conn, id, &entry.images)?;
+    set_senses(conn, id, &entry.senses)?;
+
+    Ok(id)
+}
+
+pub fn update_row(conn: &Connection, id: i64, entry: &Entry) -> Result<(), Error> {
+    conn.execute(
+        "UPDATE entries SET
+            page = ?1,
+            raw_html = ?2,
+            lang = ?3,
+            headword_full = ?4,
+            headword_persian = ?5,
+            headword_latin = ?6,
+            definitions = ?7,
+            position = ?8,
+            uid = ?9,
+            abjad = ?10,
+            headword_search = ?11,
+            headword_latin_search = ?12,
+            root = ?13
+        WHERE id = ?14",
+        (
+            entry.page,
+            &entry.raw_html,
+            entry.lang.as_str(),
+            &entry.headword_full,
+            &entry.headword_persian,
+            &entry.headword_latin,
+            &entry.definitions,
+            entry.position,
+            &entry.uid,
+            entry.abjad,
+            &entry.headword_search,
+            &entry.headword_latin_search,
+            &entry.root,
+            id,
         ),
     )?;
 
+    set_images(conn, id, &entry.images)?;
+    set_senses(conn, id, &entry.senses)?;
+
+    Ok(())
+}
+
+pub fn entry_exists(conn: &Connection, raw_html: &str) -> Result<bool, Error> {
+    let mut stmt = conn.prepare("SELECT COUNT(*) FROM entries WHERE raw_html = ?")?;
+    let count: usize = stmt.query_row([raw_html], |row| row.get(0))?;
+    Ok(count > 0)
+}
+
+pub fn load_page_entries(conn: &Connection, page: u16) -> Result<Vec<(i64, Entry)>, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM entries WHERE page = ? ORDER BY id",
+        ENTRY_COLUMNS
+    ))?;
+
+    let mut entries = stmt
+        .query_map([page], |row| Ok((row.get(0)?, read_entry(row)?)))?
+        .collect::<Result<Vec<(i64, Entry)>, _>>()?;
+
+    for (id, entry) in &mut entries {
+        entry.images = load_images(conn, *id)?;
+        entry.senses = load_senses(conn, *id)?;
+    }
+
+    Ok(entries)
+}
+
+pub fn for_each_entry<F>(conn: &Connection, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(i64, Entry) -> Result<(), Error>,
+{
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM entries ORDER BY id",
+        ENTRY_COLUMNS
+    ))?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let id = row.get(0)?;
+        let mut entry = read_entry(row)?;
+        entry.images = load_images(conn, id)?;
+        entry.senses = load_senses(conn, id)?;
+
+        f(id, entry)?;
+    }
+
     Ok(())
 }
 
+// `position` is the 1-based index of the entry among the results on its page
+pub fn parse_entry(page: u16, position: usize, raw_html: String) -> Result<Entry, Error> {
+    parse_entry_with(page, position, raw_html, AbjadPrefs::default())
+}
+
+// For a DB whose abjad values follow other preferences (see `abjad_values::load_abjad_prefs`)
+pub fn parse_entry_with(
+    page: u16,
+    position: usize,
+    raw_html: String,
+    prefs: AbjadPrefs,
+) -> Result<Entry, Error> {
+    let parsed = Html::parse_fragment(&raw_html);
+    let at = |e: Error| e.at(Location::entry(page, position));
+
+    let lang = get_lang(&parsed).map_err(at)?;
+
+    let headword_full = select_full_headword(&parsed).map_err(at)?;
+    let headword_persian = get_hw_per(&parsed).map_err(at)?;
+    let headword_latin = get_hw_lat(&parsed).map_err(at)?;
+
+    let definitions = except_headword(&raw_html).map_err(at)?;
+    let images = get_images(&parsed);
+
+    let mut entry = Entry {
+        page,
+        position,
+        raw_html,
+        lang,
+        headword_full,
+        headword_persian,
+        headword_latin,
+        definitions,
+        images,
+        ..Entry::default()
+    };
+    entry.derive_fields(prefs);
+
+    Ok(entry)
+}
+
+// An in-memory DB holding the given entries (page and raw HTML), each placed after those before
+// it on the same page
+#[cfg(test)]
+pub(crate) fn test_db(entries: &[(u16, &str)]) -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    ensure_table(&conn).unwrap();
+
+    for (i, (page, html)) in entries.iter().enumerate() {
+        let position = entries[..i].iter().filter(|(p, _)| p == page).count() + 1;
+        let entry = parse_entry(*page, position, (*html).to_owned()).unwrap();
+        insert_row(&conn, entry).unwrap();
+    }
+
+    conn
+}
+
 //
 // Private functions
 //
 
+// Seconds since the Unix epoch, for timestamps in the DB
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// Expects the columns in `ENTRY_COLUMNS` (column 0 is left for the ID)
+fn read_entry(row: &Row) -> Result<Entry, rusqlite::Error> {
+    let lang: String = row.get(5)?;
+
+    Ok(Entry {
+        page: row.get(1)?,
+        position: row.get(2)?,
+        uid: row.get(3)?,
+        raw_html: row.get(4)?,
+        lang: Lang::from_str(&lang)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(e)))?,
+        headword_full: row.get(6)?,
+        headword_persian: row.get(7)?,
+        headword_latin: row.get(8)?,
+        definitions: row.get(9)?,
+        abjad: row.get(10)?,
+        headword_search: row.get(11)?,
+        headword_latin_search: row.get(12)?,
+        root: row.get(13)?,
+        images: Vec::new(),
+        senses: Vec::new(),
+    })
+}
+
 #[allow(clippy::let_and_return, clippy::similar_names)]
 fn clean_simple(input: &str) -> String {
     let mut cleaned = input.trim().to_owned();
@@ -165,23 +423,11 @@ fn clean_simple(input: &str) -> String {
     cleaned
 }
 
-fn pandoc(input: &str) -> Result<String, anyhow::Error> {
-    let mut file = NamedTempFile::new()?;
-    write!(file, "{}", input)?;
-
-    let pandoc = Command::new("pandoc")
-        .arg(file.path())
-        .arg("-f")
-        .arg("html")
-        .arg("-t")
-        .arg("markdown_strict")
-        .arg("--wrap=none")
-        .output()?;
-
-    let output = str::from_utf8(&pandoc.stdout)?;
+fn to_markdown(input: &str) -> String {
+    let output = markdown::html_to_markdown(input);
     let cleaned = output.trim().trim_start_matches(',').trim_start();
 
-    Ok(cleaned.to_owned())
+    cleaned.to_owned()
 }
 
 //
@@ -191,9 +437,13 @@ fn pandoc(input: &str) -> Result<String, anyhow::Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use corrections::Corrections;
+    use std::path::Path;
 
     #[test]
     fn confirm_html() {
+        let corrections = Corrections::load(Path::new("corrections.toml")).unwrap();
+
         let conn_dev = Connection::open("entries.sqlite").unwrap();
         let conn_backup = Connection::open("html_backup.sqlite").unwrap();
 
@@ -206,37 +456,39 @@ mod tests {
         assert_eq!(count_dev, count_backup);
 
         let mut stmt_entries_dev = conn_dev
-            .prepare("SELECT id, raw_html FROM entries")
+            .prepare("SELECT id, page, position, raw_html FROM entries")
             .unwrap();
 
         let entry_iter_dev = stmt_entries_dev
             .query_map([], |row| {
                 let id: u32 = row.get(0).unwrap();
-                let raw_html: String = row.get(1).unwrap();
-                Ok((id, raw_html))
+                let page: u16 = row.get(1).unwrap();
+                let position: usize = row.get(2).unwrap();
+                let raw_html: String = row.get(3).unwrap();
+                Ok((id, page, position, raw_html))
             })
             .unwrap();
 
         for entry in entry_iter_dev {
-            let (id, html_dev) = entry.unwrap();
-
-            // Skipping entries whose HTML has been fixed manually
-            // abjad; ātish-ālūd; Andromachus; najz/najaz
-            if html_dev.contains(".jpg")
-                || html_dev.contains("Suffused with fire")
-                || html_dev.contains("Andromachus")
-                || html_dev.contains("najaz")
-            {
-                continue;
-            }
+            let (id, page, position, html_dev) = entry.unwrap();
 
             let mut stmt_entry_backup = conn_backup
                 .prepare("SELECT raw_html FROM entries WHERE id = ?")
                 .unwrap();
 
-            let html_backup: String = stmt_entry_backup.query_row([id], |row| row.get(0)).unwrap();
+            let mut html_backup: String =
+                stmt_entry_backup.query_row([id], |row| row.get(0)).unwrap();
+
+            // Entries whose HTML has been fixed manually are listed in the corrections file
+            if let Some(correction) = corrections.find(page, position) {
+                correction.apply_html(&mut html_backup).unwrap();
+            }
 
-            assert_eq!(html_dev, html_backup);
+            assert_eq!(
+                html_dev, html_backup,
+                "p. {}, entry {} differs from the backup; record the fix with `corrections --extract`",
+                page, position
+            );
         }
     }
 
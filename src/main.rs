@@ -1,31 +1,596 @@
 #![warn(clippy::pedantic, clippy::nursery)]
 #![allow(clippy::uninlined_format_args)]
 
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write, stdin, stdout};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::thread::sleep;
 use std::time::Duration;
 
+use abjad::{AbjadPrefs, LetterOrder};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use rusqlite::Connection;
 use scraper::Html;
 
-use steingass_scraper::defs::except_headword;
-use steingass_scraper::hw_full::select_full_headword;
-use steingass_scraper::hw_lat::get_hw_lat;
-use steingass_scraper::hw_per::get_hw_per;
-use steingass_scraper::langs::get_lang;
+use steingass_scraper::abjad_values::{load_abjad_prefs, prefs_to_string, recompute_abjad};
+use steingass_scraper::anki::{DEFAULT_DECK_NAME, write_apkg};
+use steingass_scraper::articles::{ArticleFormat, dictionary_path};
+use steingass_scraper::corrections::{Correction, Corrections, FieldOverrides};
+use steingass_scraper::dictd::write_dictd;
+use steingass_scraper::epub::write_epub;
+use steingass_scraper::export::{DEFAULT_FIELDS, Field, Filter, TableFormat, write_table};
+use steingass_scraper::fts::{fts_script, rebuild_fts};
+use steingass_scraper::fuzzy::{EDIT_COST, suggest};
+use steingass_scraper::ids::{assign_page_positions, find_uid, page_positions};
+use steingass_scraper::images::{ensure_image_files_table, fetch_image, load_image, store_image};
+use steingass_scraper::inflection::lookup_inflected;
+use steingass_scraper::json::{import_json, import_jsonl, write_json, write_jsonl};
+use steingass_scraper::langs::Lang;
+use steingass_scraper::migrations::schema_version;
+use steingass_scraper::pages::{CacheMode, PageSource, ensure_pages_table, get_page, load_page};
+use steingass_scraper::progress::{
+    PageState, begin_attempt, count_by_state, load_progress, set_state,
+};
+use steingass_scraper::query::{self, Query, Script, Search};
+use steingass_scraper::stardict::write_stardict;
+use steingass_scraper::tei::write_tei;
+use steingass_scraper::xdxf::write_xdxf;
+use steingass_scraper::xrefs::{dangling_refs, resolve_cross_refs};
 use steingass_scraper::{
-    BAD_PAGES, Entry, MAX_PAGE, MIN_PAGE, count_page_entries, ensure_table, fetch_html, insert_row,
-    select_results,
+    BAD_PAGES, MAX_PAGE, MIN_PAGE, count_page_entries, ensure_table, entry_exists, insert_row,
+    load_page_entries, parse_entry, select_results, update_row,
 };
 
+//
+// CLI definition
+//
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Scraper for the DSAL edition of Steingass's Persian-English dictionary"
+)]
+struct Cli {
+    /// Path to the entries database
+    #[arg(long, global = true, default_value = "entries.sqlite")]
+    db: PathBuf,
+
+    /// Path to the store of fetched pages
+    #[arg(long, global = true, default_value = "pages.sqlite")]
+    pages_db: PathBuf,
+
+    /// Path to the file of manual corrections
+    #[arg(long, global = true, default_value = "corrections.toml")]
+    corrections: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Fetch pages and insert any entries not yet in the DB
+    Scrape {
+        #[command(flatten)]
+        pages: PageArgs,
+
+        #[command(flatten)]
+        fetch: FetchArgs,
+
+        /// Report what would be inserted without writing to the DB
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Re-process pages already marked as parsed or verified
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Confirm that DB entries match the results on each page
+    Verify {
+        #[command(flatten)]
+        pages: PageArgs,
+
+        #[command(flatten)]
+        fetch: FetchArgs,
+
+        /// Also set each entry's position (and uid) from where its HTML appears on the page
+        #[arg(long)]
+        positions: bool,
+    },
+
+    /// Re-run extraction on the stored raw HTML and update derived columns
+    Reparse {
+        #[command(flatten)]
+        pages: PageArgs,
+
+        /// Report changes without writing to the DB
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Write all entries to a file (or stdout)
+    Export(ExportArgs),
+
+    /// Load entries from a JSON or JSON Lines export into an empty DB
+    Import {
+        /// Input path; omit to read from stdin
+        input: Option<PathBuf>,
+
+        #[arg(long, value_enum, default_value_t = ImportFormat::Jsonl)]
+        format: ImportFormat,
+    },
+
+    /// Write dictionary files for offline and e-ink readers
+    Dictionary {
+        /// Output path without extensions, e.g., "dist/steingass"
+        output: PathBuf,
+
+        #[arg(long, value_enum)]
+        format: DictionaryFormat,
+
+        /// How to write the definitions (`StarDict` and dictd only)
+        #[arg(long, value_enum, default_value_t = Articles::Html)]
+        articles: Articles,
+    },
+
+    /// Write an Anki deck with cards from Persian to English and from English to Persian
+    Anki {
+        /// Output path, e.g., "steingass.apkg"
+        output: PathBuf,
+
+        /// Name of the deck in Anki
+        #[arg(long, default_value = DEFAULT_DECK_NAME)]
+        deck: String,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Rebuild the full-text tables, or write them out as a SQL script for deployment
+    Fts {
+        /// Repopulate the full-text tables from the entries table
+        #[arg(long)]
+        rebuild: bool,
+
+        /// Write the SQL script to this path (or "-" for stdout)
+        #[arg(long)]
+        script: Option<PathBuf>,
+    },
+
+    /// Print summary statistics for the DB and page store
+    Stats,
+
+    /// List manual corrections, or extract them from differences between DB and page store
+    Corrections {
+        #[command(flatten)]
+        pages: PageArgs,
+
+        /// Add a correction for each DB entry that differs from a fresh parse of its page
+        #[arg(long)]
+        extract: bool,
+    },
+
+    /// Recompute abjad values, optionally under an alternate convention
+    Abjad {
+        /// Count shaddah, doubling the value of the preceding letter
+        #[arg(long)]
+        count_shaddah: bool,
+
+        /// Count alif maddah as two alifs
+        #[arg(long)]
+        double_alif_maddah: bool,
+
+        /// Give a lone hamzah no value
+        #[arg(long)]
+        ignore_lone_hamzah: bool,
+
+        /// Use the Maghribi letter order
+        #[arg(long)]
+        maghribi: bool,
+    },
+
+    /// Extract cross-references from definitions and resolve them to entries
+    Xrefs {
+        /// List references that could not be resolved to exactly one entry
+        #[arg(long)]
+        dangling: bool,
+    },
+
+    /// Look up entries by headword, definition text, page or abjad value
+    Search(SearchArgs),
+
+    /// Look up a word as it appears in running Persian text, stripping inflectional affixes
+    Lookup {
+        /// E.g. "کتاب‌هایم" or "می‌رفتند"
+        word: String,
+    },
+
+    /// Suggest headwords close to one that is not in the DB
+    Suggest {
+        /// Persian headword, or a transliteration with --latin
+        query: String,
+
+        /// Compare against transliterations rather than Persian headwords
+        #[arg(long)]
+        latin: bool,
+
+        /// Greatest distance to allow; confusable letters (e.g. س/ص) count 1, other edits 2
+        #[arg(long, default_value_t = 2 * EDIT_COST)]
+        max_distance: u32,
+
+        /// Maximum number of suggestions
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+}
+
+#[derive(Args)]
+struct PageArgs {
+    /// Page range, e.g. 1-100 or 5 (defaults to every page)
+    #[arg(long, value_parser = parse_range, conflicts_with = "page")]
+    pages: Option<RangeInclusive<u16>>,
+
+    /// Specific pages, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    page: Vec<u16>,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Output path; omit to write to stdout
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = ExportFormat::Tsv)]
+    format: ExportFormat,
+
+    /// Leave out the raw HTML of each entry (JSON formats only); such an export cannot be
+    /// imported
+    #[arg(long)]
+    no_raw_html: bool,
+
+    /// Columns to write, comma-separated (TSV and CSV only): id, uid, page, position, lang,
+    /// `lang_code`, `headword_full`, `headword_persian`, `headword_latin`, definitions, abjad, root
+    #[arg(long, value_delimiter = ',')]
+    fields: Vec<Field>,
+
+    /// Filters on the entries to write (TSV and CSV only)
+    #[command(flatten)]
+    filter: FilterArgs,
+}
+
+#[derive(Args)]
+struct FilterArgs {
+    /// Only entries marked with this language, e.g. "Arabic"
+    #[arg(long)]
+    lang: Option<Lang>,
+
+    /// Only entries on these pages, e.g. 1-100 or 5
+    #[arg(long, value_parser = parse_range)]
+    pages: Option<RangeInclusive<u16>>,
+
+    /// Only entries with abjad values in this range, e.g. 1-100 or 66
+    #[arg(long, value_parser = parse_abjad_range)]
+    abjad: Option<RangeInclusive<u32>>,
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// Headword, transliteration, phrase or full-text query, according to --in
+    #[arg(required_unless_present_any = ["page", "abjad", "root"])]
+    query: Option<String>,
+
+    /// What to match the query against
+    #[arg(long = "in", value_enum, default_value_t = SearchField::Persian)]
+    field: SearchField,
+
+    /// Match headwords (or phrases) that begin with the query
+    #[arg(long)]
+    prefix: bool,
+
+    /// List the entries on a page
+    #[arg(long, conflicts_with_all = ["query", "abjad", "root"])]
+    page: Option<u16>,
+
+    /// List the entries with an abjad value
+    #[arg(long, conflicts_with_all = ["query", "root"])]
+    abjad: Option<u32>,
+
+    /// List the Arabic words derived from a root, e.g. "ک-ت-ب"
+    #[arg(long, conflicts_with = "query")]
+    root: Option<String>,
+
+    /// Only entries marked with this language, e.g. "Arabic"
+    #[arg(long)]
+    lang: Option<Lang>,
+
+    /// Maximum number of results
+    #[arg(long)]
+    limit: Option<usize>,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Tsv,
+    Csv,
+    Json,
+    Jsonl,
+    /// TEI Lex-0 XML
+    Tei,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ImportFormat {
+    Json,
+    Jsonl,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DictionaryFormat {
+    /// .ifo, .idx and .dict.dz
+    Stardict,
+    /// .index and .dict.dz
+    Dictd,
+    /// .xdxf
+    Xdxf,
+    /// .epub, with Kindle dictionary markup
+    Epub,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Articles {
+    Html,
+    Text,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SearchField {
+    /// Persian headwords, normalized
+    Persian,
+    /// Transliterations, folded to ASCII
+    Latin,
+    /// Compounds and idioms within the senses, normalized
+    Phrases,
+    /// Definitions, in FTS5 query syntax
+    Definitions,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+struct FetchArgs {
+    /// Read pages only from the page store; never touch the network
+    #[arg(long, conflicts_with = "refresh")]
+    offline: bool,
+
+    /// Re-download pages even if they are already in the page store
+    #[arg(long)]
+    refresh: bool,
+
+    /// Seconds to wait after each network request
+    #[arg(long, default_value_t = 3)]
+    delay: u64,
+
+    /// Also download images referenced by entries into the page store
+    #[arg(long)]
+    images: bool,
+}
+
+impl PageArgs {
+    fn selected(&self) -> Vec<u16> {
+        let mut selected: Vec<u16> = if self.page.is_empty() {
+            self.pages.clone().unwrap_or(MIN_PAGE..=MAX_PAGE).collect()
+        } else {
+            self.page.clone()
+        };
+
+        selected.sort_unstable();
+        selected.dedup();
+        selected
+    }
+}
+
+impl FilterArgs {
+    const fn is_set(&self) -> bool {
+        self.lang.is_some() || self.pages.is_some() || self.abjad.is_some()
+    }
+
+    const fn filter(self) -> Filter {
+        Filter {
+            lang: self.lang,
+            pages: self.pages,
+            abjad: self.abjad,
+        }
+    }
+}
+
+impl SearchArgs {
+    fn search(&self) -> Search {
+        let query = match (&self.query, self.page, self.abjad, &self.root) {
+            (_, Some(page), _, _) => Query::Page(page),
+            (_, _, Some(abjad), _) => Query::Abjad(abjad),
+            (_, _, _, Some(root)) => Query::Root(root.clone()),
+            (text, None, None, None) => {
+                // Clap requires the query when there is nothing else to go on
+                let text = text.clone().unwrap_or_default();
+
+                match self.field {
+                    SearchField::Persian => Query::Headword {
+                        script: Script::Persian,
+                        text,
+                        prefix: self.prefix,
+                    },
+                    SearchField::Latin => Query::Headword {
+                        script: Script::Latin,
+                        text,
+                        prefix: self.prefix,
+                    },
+                    SearchField::Phrases => Query::Phrase {
+                        text,
+                        prefix: self.prefix,
+                    },
+                    SearchField::Definitions => Query::Definitions(text),
+                }
+            }
+        };
+
+        Search {
+            query,
+            lang: self.lang,
+            limit: self.limit,
+        }
+    }
+}
+
+impl FetchArgs {
+    const fn mode(&self) -> CacheMode {
+        if self.offline {
+            CacheMode::Offline
+        } else if self.refresh {
+            CacheMode::Refresh
+        } else {
+            CacheMode::ReadThrough
+        }
+    }
+}
+
+//
+// Main
+//
+
 fn main() -> Result<(), anyhow::Error> {
-    println!("Ensuring DB connection...");
-    let conn = Connection::open("entries.sqlite")?;
+    let cli = Cli::parse();
+
+    // The script needs no DB; opening one would migrate it, which a deploy should never do
+    if let Command::Fts {
+        rebuild: false,
+        script: Some(path),
+    } = &cli.command
+    {
+        return write_fts_script(path);
+    }
+
+    let conn = Connection::open(&cli.db)?;
     ensure_table(&conn)?;
 
-    let start_page = MIN_PAGE;
-    let stop_page = MAX_PAGE;
+    let corrections = Corrections::load(&cli.corrections)?;
+
+    match cli.command {
+        Command::Scrape {
+            pages,
+            fetch,
+            dry_run,
+            force,
+        } => {
+            let conn_pages = open_pages(&cli.pages_db)?;
+            scrape(
+                &conn,
+                &conn_pages,
+                &corrections,
+                &pages.selected(),
+                &fetch,
+                dry_run,
+                force,
+            )?;
+        }
+        Command::Verify {
+            pages,
+            fetch,
+            positions,
+        } => {
+            let conn_pages = open_pages(&cli.pages_db)?;
+            let pages = pages.selected();
+            verify(&conn, &conn_pages, &corrections, &pages, &fetch, positions)?;
+        }
+        Command::Reparse { pages, dry_run } => {
+            reparse(&conn, &corrections, &pages.selected(), dry_run)?;
+        }
+        Command::Export(args) => export(&conn, args)?,
+        Command::Import { input, format } => import(&conn, input, format)?,
+        Command::Dictionary {
+            output,
+            format,
+            articles,
+        } => dictionary(&conn, &output, format, articles)?,
+        Command::Anki {
+            output,
+            deck,
+            filter,
+        } => anki(&conn, &output, &deck, &filter.filter())?,
+        Command::Fts { rebuild, script } => fts(&conn, rebuild, script)?,
+        Command::Stats => stats(&conn, &cli.pages_db)?,
+        Command::Corrections { pages, extract } => {
+            if extract {
+                let pages = pages.selected();
+                extract_corrections(&conn, &cli.pages_db, corrections, &pages, &cli.corrections)?;
+            } else {
+                list_corrections(&corrections);
+            }
+        }
+        Command::Abjad {
+            count_shaddah,
+            double_alif_maddah,
+            ignore_lone_hamzah,
+            maghribi,
+        } => {
+            let prefs = AbjadPrefs {
+                count_shaddah,
+                double_alif_maddah,
+                ignore_lone_hamzah,
+                letter_order: if maghribi {
+                    LetterOrder::Maghribi
+                } else {
+                    LetterOrder::Mashriqi
+                },
+            };
+
+            let count = recompute_abjad(&conn, prefs)?;
+            println!(
+                "Recomputed {} abjad values ({})",
+                count,
+                prefs_to_string(prefs)
+            );
+        }
+        Command::Xrefs { dangling } => xrefs(&conn, dangling)?,
+        Command::Search(args) => print_search(&conn, &args.search(), args.format)?,
+        Command::Lookup { word } => lookup(&conn, &word)?,
+        Command::Suggest {
+            query,
+            latin,
+            max_distance,
+            limit,
+        } => print_suggestions(&conn, script(latin), &query, max_distance, limit)?,
+    }
+
+    Ok(())
+}
+
+//
+// Subcommands
+//
+
+fn scrape(
+    conn: &Connection,
+    conn_pages: &Connection,
+    corrections: &Corrections,
+    pages: &[u16],
+    fetch: &FetchArgs,
+    dry_run: bool,
+    force: bool,
+) -> Result<(), anyhow::Error> {
+    let mut failed = Vec::new();
 
-    for page in start_page..=stop_page {
+    for &page in pages {
         println!("----------------");
 
         if BAD_PAGES.contains(&page) {
@@ -33,95 +598,783 @@ fn main() -> Result<(), anyhow::Error> {
             continue;
         }
 
-        if page > start_page {
-            println!("Pausing for 3 seconds...");
-            sleep(Duration::from_secs(3));
+        if !force
+            && let Some(progress) = load_progress(conn, page)?
+            && progress.state.is_done()
+        {
+            println!("p. {} already {}; skipping", page, progress.state.as_str());
+            continue;
         }
 
-        println!("Fetching p. {}...", page);
-        let page_html = fetch_html(page)?;
+        if !dry_run {
+            begin_attempt(conn, page)?;
+        }
 
-        let results = select_results(&page_html);
-        let results_count = results.len();
-        println!("Found {} entries on p. {}", results_count, page);
+        if let Err(e) = scrape_page(conn, conn_pages, corrections, page, fetch, dry_run) {
+            // Any rows inserted for the page were rolled back with its transaction
+            println!("p. {} failed: {:#}", page, e);
 
-        let db_count = count_page_entries(&conn, page)?;
-        println!("Rows for p. {} in DB: {}", page, db_count);
+            if !dry_run {
+                set_state(
+                    conn,
+                    page,
+                    PageState::Failed,
+                    None,
+                    Some(&format!("{:#}", e)),
+                )?;
+            }
 
-        // Scraping has been completed; this is to confirm that DB entries match fetched results
-        assert_eq!(db_count, results_count);
+            failed.push(page);
+        }
+    }
 
-        // if db_count == results_count {
-        //     println!("No further entries for p. {}", page);
-        //     continue;
-        // }
+    println!("----------------");
 
-        // assert!(db_count == 0, "Partial coverage in DB for p. {}", page);
+    if !failed.is_empty() {
+        anyhow::bail!("{} pages failed: {:?}", failed.len(), failed);
+    }
 
-        for (i, result) in results.iter().enumerate() {
-            let html = result.html();
+    println!("Done");
 
-            // The entry for "abjad" on p. 5 has an img tag, which can cause problems
-            // Everything else is fine, and I've manually checked the "abjad" entry
-            if html.contains(".jpg") {
-                println!(
-                    "p. {}, entry {}/{}: Skipping problematic entry",
-                    page,
-                    i + 1,
-                    results_count
-                );
-                continue;
+    Ok(())
+}
+
+fn scrape_page(
+    conn: &Connection,
+    conn_pages: &Connection,
+    corrections: &Corrections,
+    page: u16,
+    fetch: &FetchArgs,
+    dry_run: bool,
+) -> Result<(), anyhow::Error> {
+    let mut results = load_results(conn_pages, page, fetch)?;
+    let results_count = results.len();
+    println!("Found {} entries on p. {}", results_count, page);
+
+    let corrected = corrections.apply_html(page, &mut results)?;
+    if corrected > 0 {
+        println!("Applied {} HTML corrections on p. {}", corrected, page);
+    }
+
+    if !dry_run {
+        set_state(conn, page, PageState::Fetched, Some(results_count), None)?;
+    }
+
+    let prefs = load_abjad_prefs(conn)?;
+
+    // Either every entry on the page goes in, or none of them does
+    let tx = conn.unchecked_transaction()?;
+
+    for (i, html) in results.into_iter().enumerate() {
+        let entry = corrections.parse_entry(page, i + 1, html, prefs)?;
+
+        if find_uid(&tx, &entry.uid)?.is_some() {
+            continue;
+        }
+
+        if fetch.images && !dry_run {
+            for image in &entry.images {
+                download_image(conn_pages, &image.src, page, fetch)?;
             }
+        }
 
-            let mut stmt = conn.prepare(
-                "SELECT COUNT(*)
-                FROM entries
-                WHERE raw_html = ?",
-            )?;
-            let count: u32 = stmt.query_row([&html], |row| row.get(0))?;
-            if count == 1 {
-                println!(
-                    "p. {}, entry {}/{}: Exactly one exact match in DB",
+        if dry_run {
+            println!(
+                "p. {}, entry {}/{}: Would insert {}",
+                page,
+                i + 1,
+                results_count,
+                entry.headword_full
+            );
+            continue;
+        }
+
+        insert_row(&tx, entry)?;
+        println!("Inserted entry {}/{} for p. {}", i + 1, results_count, page);
+    }
+
+    if !dry_run {
+        set_state(&tx, page, PageState::Parsed, Some(results_count), None)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+fn verify(
+    conn: &Connection,
+    conn_pages: &Connection,
+    corrections: &Corrections,
+    pages: &[u16],
+    fetch: &FetchArgs,
+    positions: bool,
+) -> Result<(), anyhow::Error> {
+    let mut mismatches = 0;
+
+    for &page in pages {
+        if BAD_PAGES.contains(&page) {
+            continue;
+        }
+
+        match verify_page(conn, conn_pages, corrections, page, fetch, positions) {
+            Ok(results_count) => {
+                set_state(conn, page, PageState::Verified, Some(results_count), None)?;
+            }
+            Err(e) => {
+                println!("p. {}: {:#}", page, e);
+                set_state(
+                    conn,
                     page,
-                    i + 1,
-                    results_count,
-                );
+                    PageState::Failed,
+                    None,
+                    Some(&format!("{:#}", e)),
+                )?;
+                mismatches += 1;
+            }
+        }
+    }
+
+    println!("Checked {} pages; {} mismatches", pages.len(), mismatches);
+
+    if mismatches > 0 {
+        anyhow::bail!("Verification failed");
+    }
+
+    Ok(())
+}
+
+fn verify_page(
+    conn: &Connection,
+    conn_pages: &Connection,
+    corrections: &Corrections,
+    page: u16,
+    fetch: &FetchArgs,
+    positions: bool,
+) -> Result<usize, anyhow::Error> {
+    let mut results = load_results(conn_pages, page, fetch)?;
+
+    let corrected = corrections.apply_html(page, &mut results)?;
+    if corrected > 0 {
+        println!("p. {}: {} entries matched via corrections", page, corrected);
+    }
+
+    let db_count = count_page_entries(conn, page)?;
+
+    if db_count != results.len() {
+        anyhow::bail!("{} entries on page, {} rows in DB", results.len(), db_count);
+    }
+
+    if positions {
+        let tx = conn.unchecked_transaction()?;
+        let placements = assign_page_positions(&tx, page, &results)?;
+        tx.commit()?;
+
+        for placement in placements.iter().filter(|p| !p.matched) {
+            println!(
+                "p. {}: ID {} matches no entry; placed at {} by elimination",
+                page, placement.id, placement.position
+            );
+        }
+    }
+
+    for (i, html) in results.iter().enumerate() {
+        if !entry_exists(conn, html)? {
+            anyhow::bail!("Entry {} has no exact match in DB", i + 1);
+        }
+    }
+
+    Ok(results.len())
+}
+
+fn reparse(
+    conn: &Connection,
+    corrections: &Corrections,
+    pages: &[u16],
+    dry_run: bool,
+) -> Result<(), anyhow::Error> {
+    let prefs = load_abjad_prefs(conn)?;
+    let mut changed = 0;
+    let mut failed = 0;
+
+    for &page in pages {
+        for (id, entry) in load_page_entries(conn, page)? {
+            // A bad entry is reported and left as it is; the rest of the run continues
+            let mut regen = match corrections.parse_entry(
+                entry.page,
+                entry.position,
+                entry.raw_html.clone(),
+                prefs,
+            ) {
+                Ok(regen) => regen,
+                Err(e) => {
+                    println!("ID {}: {}", id, e);
+                    failed += 1;
+                    continue;
+                }
+            };
+
+            // A row keeps its uid, even if a correction added since changes its headword
+            regen.uid.clone_from(&entry.uid);
+
+            if regen == entry {
                 continue;
             }
 
-            assert_eq!(count, 1, "{}", html); // Remaining code in this loop is unreachable
+            println!("p. {}, ID {}: {}", page, id, regen.headword_full);
+            changed += 1;
+
+            if !dry_run {
+                update_row(conn, id, &regen)?;
+            }
+        }
+    }
+
+    let verb = if dry_run { "would change" } else { "changed" };
+    println!(
+        "Reparsed {} pages; {} entries {}; {} failed",
+        pages.len(),
+        changed,
+        verb,
+        failed
+    );
+
+    Ok(())
+}
+
+fn export(conn: &Connection, args: ExportArgs) -> Result<(), anyhow::Error> {
+    let table = matches!(args.format, ExportFormat::Tsv | ExportFormat::Csv);
+    let json = matches!(args.format, ExportFormat::Json | ExportFormat::Jsonl);
+
+    // An unfiltered dump could be taken for the one asked for
+    if !table && (!args.fields.is_empty() || args.filter.is_set()) {
+        anyhow::bail!("--fields, --lang, --pages and --abjad apply only to TSV and CSV exports");
+    }
+    if !json && args.no_raw_html {
+        anyhow::bail!("--no-raw-html applies only to JSON and JSONL exports");
+    }
+
+    let raw_html = !args.no_raw_html;
+    let filter = args.filter.filter();
+    let fields = if args.fields.is_empty() {
+        &DEFAULT_FIELDS
+    } else {
+        args.fields.as_slice()
+    };
+
+    let mut out: Box<dyn Write> = match args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(stdout().lock())),
+    };
+
+    let count = match args.format {
+        ExportFormat::Tsv => write_table(conn, &mut out, TableFormat::Tsv, fields, &filter)?,
+        ExportFormat::Csv => write_table(conn, &mut out, TableFormat::Csv, fields, &filter)?,
+        ExportFormat::Json => write_json(conn, &mut out, raw_html)?,
+        ExportFormat::Jsonl => write_jsonl(conn, &mut out, raw_html)?,
+        ExportFormat::Tei => write_tei(conn, &mut out)?,
+    };
+    out.flush()?;
+
+    eprintln!("Exported {} entries", count);
+
+    Ok(())
+}
+
+fn import(
+    conn: &Connection,
+    input: Option<PathBuf>,
+    format: ImportFormat,
+) -> Result<(), anyhow::Error> {
+    let reader: Box<dyn Read> = match input {
+        Some(path) => Box::new(File::open(path)?),
+        None => Box::new(stdin().lock()),
+    };
+    let reader = BufReader::new(reader);
+
+    let count = match format {
+        ImportFormat::Json => import_json(conn, reader)?,
+        ImportFormat::Jsonl => import_jsonl(conn, reader)?,
+    };
+
+    println!("Imported {} entries", count);
+
+    Ok(())
+}
+
+fn dictionary(
+    conn: &Connection,
+    output: &Path,
+    format: DictionaryFormat,
+    articles: Articles,
+) -> Result<(), anyhow::Error> {
+    let articles = match articles {
+        Articles::Html => ArticleFormat::Html,
+        Articles::Text => ArticleFormat::Text,
+    };
+
+    let count = match format {
+        DictionaryFormat::Stardict => write_stardict(conn, output, articles)?,
+        DictionaryFormat::Dictd => write_dictd(conn, output, articles)?,
+        DictionaryFormat::Xdxf => {
+            let mut out = BufWriter::new(File::create(dictionary_path(output, ".xdxf"))?);
+            let count = write_xdxf(conn, &mut out)?;
+            out.flush()?;
+            count
+        }
+        DictionaryFormat::Epub => write_epub(
+            conn,
+            BufWriter::new(File::create(dictionary_path(output, ".epub"))?),
+        )?,
+    };
+
+    println!("Wrote {} entries to {}.*", count, output.display());
+
+    Ok(())
+}
+
+fn anki(
+    conn: &Connection,
+    output: &Path,
+    deck: &str,
+    filter: &Filter,
+) -> Result<(), anyhow::Error> {
+    let count = write_apkg(conn, BufWriter::new(File::create(output)?), deck, filter)?;
+
+    println!("Wrote {} notes to {}", count, output.display());
+
+    Ok(())
+}
+
+fn fts(conn: &Connection, rebuild: bool, script: Option<PathBuf>) -> Result<(), anyhow::Error> {
+    if rebuild {
+        rebuild_fts(conn)?;
+        println!("Rebuilt full-text tables");
+    }
+
+    if let Some(path) = script {
+        write_fts_script(&path)?;
+    }
+
+    Ok(())
+}
+
+fn write_fts_script(path: &Path) -> Result<(), anyhow::Error> {
+    if path.as_os_str() == "-" {
+        print!("{}", fts_script());
+    } else {
+        std::fs::write(path, fts_script())?;
+        eprintln!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn stats(conn: &Connection, pages_db: &PathBuf) -> Result<(), anyhow::Error> {
+    let entries: usize = conn.query_row("SELECT COUNT(*) FROM entries", [], |row| row.get(0))?;
+    let pages: usize = conn.query_row("SELECT COUNT(DISTINCT page) FROM entries", [], |row| {
+        row.get(0)
+    })?;
+    let expected_pages = usize::from(MAX_PAGE - MIN_PAGE + 1) - BAD_PAGES.len();
+
+    println!("Schema version: {}", schema_version(conn)?);
+    println!("Entries: {}", entries);
+    println!("Pages with entries: {}/{}", pages, expected_pages);
+
+    println!("Entries by language:");
+
+    let mut stmt =
+        conn.prepare("SELECT lang, COUNT(*) FROM entries GROUP BY lang ORDER BY COUNT(*) DESC")?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let lang: String = row.get(0)?;
+        let count: usize = row.get(1)?;
+        println!("    {:>6}  {}", count, lang);
+    }
+
+    let progress = count_by_state(conn)?;
+
+    if !progress.is_empty() {
+        println!("Scrape progress by page:");
+
+        for (state, count) in progress {
+            println!("    {:>6}  {}", count, state.as_str());
+        }
+    }
+
+    if pages_db.exists() {
+        let conn_pages = open_pages(pages_db)?;
+        let stored: usize =
+            conn_pages.query_row("SELECT COUNT(*) FROM pages", [], |row| row.get(0))?;
+        println!("Pages in store: {}", stored);
+    }
+
+    Ok(())
+}
+
+fn list_corrections(corrections: &Corrections) {
+    for correction in &corrections.corrections {
+        let mut parts = Vec::new();
+
+        if correction.raw_html.is_some() {
+            parts.push("raw_html");
+        }
+
+        let fields = &correction.fields;
+        for (name, value) in [
+            ("lang", &fields.lang),
+            ("headword_full", &fields.headword_full),
+            ("headword_persian", &fields.headword_persian),
+            ("headword_latin", &fields.headword_latin),
+            ("definitions", &fields.definitions),
+        ] {
+            if value.is_some() {
+                parts.push(name);
+            }
+        }
+
+        println!(
+            "p. {}, entry {}: {} ({})",
+            correction.page,
+            correction.position,
+            correction.note.as_deref().unwrap_or("no note"),
+            parts.join(", ")
+        );
+    }
+
+    println!("{} corrections", corrections.corrections.len());
+}
+
+// Records hand edits made directly in the DB, so that they can be re-applied after re-scraping
+fn extract_corrections(
+    conn: &Connection,
+    pages_db: &PathBuf,
+    mut corrections: Corrections,
+    pages: &[u16],
+    path: &Path,
+) -> Result<(), anyhow::Error> {
+    let conn_pages = open_pages(pages_db)?;
+    let mut added = 0;
+    let mut failed = 0;
+
+    for &page in pages {
+        if BAD_PAGES.contains(&page) {
+            continue;
+        }
+
+        let Some(stored) = load_page(&conn_pages, page)? else {
+            println!("p. {} is not in the page store; skipping", page);
+            continue;
+        };
+
+        let page_html = Html::parse_document(&stored.html);
+        let results: Vec<String> = select_results(&page_html)
+            .iter()
+            .map(scraper::ElementRef::html)
+            .collect();
+        let db_entries = load_page_entries(conn, page)?;
 
-            let parsed = Html::parse_fragment(&html);
+        // Rows are matched to the page by their HTML, not their order in the DB
+        let mut corrected = results.clone();
+        corrections.apply_html(page, &mut corrected)?;
+        let rows: Vec<(i64, &str)> = db_entries
+            .iter()
+            .map(|(id, entry)| (*id, entry.raw_html.as_str()))
+            .collect();
 
-            let lang = get_lang(&parsed);
+        let placements = match page_positions(page, &rows, &corrected) {
+            Ok(placements) => placements,
+            Err(e) => {
+                println!("{}; skipping", e);
+                continue;
+            }
+        };
 
-            let headword_full = select_full_headword(&parsed)?;
-            let headword_persian = get_hw_per(&parsed);
-            let headword_latin = get_hw_lat(&parsed)?;
+        for ((_, entry), placement) in db_entries.iter().zip(placements) {
+            let position = placement.position;
+            let html = &results[position - 1];
 
-            let definitions = except_headword(&html)?;
+            if corrections.find(page, position).is_some() {
+                continue;
+            }
 
-            let entry = Entry {
+            let mut correction = Correction {
                 page,
-                raw_html: html,
-                lang,
-                headword_full,
-                headword_persian,
-                headword_latin,
-                definitions,
+                position,
+                note: Some(format!("Extracted from DB: {}", entry.headword_full)),
+                ..Correction::default()
             };
 
-            insert_row(&conn, entry)?;
-            println!("Inserted entry {}/{} for p. {}", i + 1, results_count, page);
+            if &entry.raw_html != html {
+                correction.raw_html = Some(entry.raw_html.clone());
+            }
+
+            // A bad entry is reported; the fixes found in the rest are still recorded
+            let regen = match parse_entry(page, position, entry.raw_html.clone()) {
+                Ok(regen) => regen,
+                Err(e) => {
+                    println!("p. {}, entry {}: {}", page, position, e);
+                    failed += 1;
+                    continue;
+                }
+            };
+            correction.fields = FieldOverrides::between(&regen, entry);
+
+            if correction.raw_html.is_some() || !correction.fields.is_empty() {
+                println!("p. {}, entry {}: {}", page, position, entry.headword_full);
+                corrections.corrections.push(correction);
+                added += 1;
+            }
         }
+    }
+
+    corrections
+        .corrections
+        .sort_by_key(|c| (c.page, c.position));
+    corrections.save(path)?;
+
+    println!(
+        "Added {} corrections to {}; {} entries failed",
+        added,
+        path.display(),
+        failed
+    );
 
-        // let db_count_new = count_page_entries(&conn, page)?;
-        // println!("Updated row count for p. {} in DB: {}", page, db_count_new);
-        // assert_eq!(db_count_new, results_count);
+    if failed > 0 {
+        anyhow::bail!("Extraction incomplete");
     }
 
-    println!("----------------");
-    println!("Done");
+    Ok(())
+}
+
+fn xrefs(conn: &Connection, dangling: bool) -> Result<(), anyhow::Error> {
+    let counts = resolve_cross_refs(conn)?;
+
+    println!("Resolved: {}", counts.resolved);
+    println!("Ambiguous: {}", counts.ambiguous);
+    println!("Unresolved: {}", counts.unresolved);
+
+    if dangling {
+        for dangling_ref in dangling_refs(conn)? {
+            println!(
+                "p. {}, {} ({}): {} [{}]",
+                dangling_ref.page,
+                dangling_ref.entry_uid,
+                dangling_ref.headword_full,
+                dangling_ref.text,
+                dangling_ref.status
+            );
+        }
+    }
 
     Ok(())
 }
+
+fn print_search(
+    conn: &Connection,
+    search: &Search,
+    format: OutputFormat,
+) -> Result<(), anyhow::Error> {
+    let results = query::search(conn, search)?;
+
+    if format == OutputFormat::Json {
+        let values: Vec<serde_json::Value> = results
+            .iter()
+            .map(|(id, entry)| {
+                serde_json::json!({
+                    "id": id,
+                    "uid": entry.uid,
+                    "page": entry.page,
+                    "position": entry.position,
+                    "lang": entry.lang.as_str(),
+                    "headword_full": entry.headword_full,
+                    "headword_persian": entry.headword_persian,
+                    "headword_latin": entry.headword_latin,
+                    "definitions": entry.definitions,
+                    "abjad": entry.abjad,
+                })
+            })
+            .collect();
+
+        println!("{}", serde_json::to_string_pretty(&values)?);
+        return Ok(());
+    }
+
+    for (_, entry) in &results {
+        println!(
+            "{} [{}; p. {}, {}]",
+            entry.headword_full,
+            entry.lang.as_str(),
+            entry.page,
+            entry.uid
+        );
+        println!("    {}", entry.definitions);
+    }
+
+    eprintln!("{} results", results.len());
+
+    if let Query::Headword { script, text, .. } = &search.query
+        && results.is_empty()
+    {
+        print_suggestions(conn, *script, text, 2 * EDIT_COST, 5)?;
+    }
+
+    Ok(())
+}
+
+fn lookup(conn: &Connection, word: &str) -> Result<(), anyhow::Error> {
+    let matches = lookup_inflected(conn, word)?;
+
+    if matches.is_empty() {
+        println!("No headword found for any analysis of {}", word);
+        return Ok(());
+    }
+
+    for found in matches {
+        let affixes: Vec<&str> = found.analysis.affixes.iter().map(|a| a.label).collect();
+
+        if affixes.is_empty() {
+            println!("{} (as written)", found.analysis.lemma);
+        } else {
+            println!("{} ({})", found.analysis.lemma, affixes.join(" + "));
+        }
+
+        for (_, entry) in &found.entries {
+            println!(
+                "    {} [{}; p. {}, {}]",
+                entry.headword_full,
+                entry.lang.as_str(),
+                entry.page,
+                entry.uid
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn print_suggestions(
+    conn: &Connection,
+    script: Script,
+    query: &str,
+    max_distance: u32,
+    limit: usize,
+) -> Result<(), anyhow::Error> {
+    let suggestions = suggest(conn, script, query, max_distance, limit)?;
+
+    if suggestions.is_empty() {
+        println!("No headwords within distance {}", max_distance);
+        return Ok(());
+    }
+
+    println!("Did you mean:");
+
+    for suggestion in suggestions {
+        println!(
+            "    {} (distance {}; IDs {:?})",
+            suggestion.headword, suggestion.distance, suggestion.ids
+        );
+    }
+
+    Ok(())
+}
+
+//
+// Helpers
+//
+
+fn open_pages(path: &PathBuf) -> Result<Connection, anyhow::Error> {
+    let conn_pages = Connection::open(path)?;
+    ensure_pages_table(&conn_pages)?;
+    ensure_image_files_table(&conn_pages)?;
+    Ok(conn_pages)
+}
+
+fn download_image(
+    conn_pages: &Connection,
+    url: &str,
+    page: u16,
+    fetch: &FetchArgs,
+) -> Result<(), anyhow::Error> {
+    if fetch.offline || (!fetch.refresh && load_image(conn_pages, url)?.is_some()) {
+        return Ok(());
+    }
+
+    let stored = fetch_image(url, page)?;
+    store_image(conn_pages, &stored)?;
+
+    println!(
+        "Downloaded {} (HTTP status {}); pausing for {} seconds...",
+        url, stored.status, fetch.delay
+    );
+    sleep(Duration::from_secs(fetch.delay));
+
+    Ok(())
+}
+
+// Returns the raw HTML of each result on the page
+fn load_results(
+    conn_pages: &Connection,
+    page: u16,
+    fetch: &FetchArgs,
+) -> Result<Vec<String>, anyhow::Error> {
+    let (stored, source) = get_page(conn_pages, page, fetch.mode())?;
+
+    if source == PageSource::Network {
+        println!(
+            "Fetched p. {}; pausing for {} seconds...",
+            page, fetch.delay
+        );
+        sleep(Duration::from_secs(fetch.delay));
+    }
+
+    if !stored.is_success() {
+        anyhow::bail!("HTTP status {}", stored.status);
+    }
+
+    let page_html = Html::parse_document(&stored.html);
+    let results = select_results(&page_html)
+        .iter()
+        .map(scraper::ElementRef::html)
+        .collect();
+
+    Ok(results)
+}
+
+const fn script(latin: bool) -> Script {
+    if latin {
+        Script::Latin
+    } else {
+        Script::Persian
+    }
+}
+
+fn parse_range(input: &str) -> Result<RangeInclusive<u16>, String> {
+    parse_bounds(input, "page")
+}
+
+fn parse_abjad_range(input: &str) -> Result<RangeInclusive<u32>, String> {
+    parse_bounds(input, "abjad value")
+}
+
+fn parse_bounds<T: FromStr + PartialOrd>(
+    input: &str,
+    what: &str,
+) -> Result<RangeInclusive<T>, String> {
+    let (start, end) = input.split_once('-').unwrap_or((input, input));
+
+    let start: T = start
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid {}: {}", what, start))?;
+    let end: T = end
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid {}: {}", what, end))?;
+
+    if start > end {
+        return Err(format!("Invalid range: {}", input));
+    }
+
+    Ok(start..=end)
+}
@@ -0,0 +1,319 @@
+use scraper::{ElementRef, Html, Node};
+
+//
+// Type definitions
+//
+
+// A reduced version of pandoc's inline AST; enough to cover the tags used in Steingass
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Inline {
+    Str(String),
+    Space,
+    LineBreak,
+    Emph(Vec<Self>),
+    Strong(Vec<Self>),
+    Superscript(Vec<Self>),
+    Image {
+        alt: Vec<Self>,
+        src: String,
+        title: String,
+    },
+}
+
+//
+// Public functions
+//
+
+// Reproduces `pandoc -f html -t markdown_strict --wrap=none` for Steingass entry fragments
+#[must_use]
+pub fn html_to_markdown(input: &str) -> String {
+    let parsed = Html::parse_fragment(input);
+
+    let mut blocks = Vec::new();
+    let mut current = Vec::new();
+
+    walk_blocks(parsed.root_element(), &mut blocks, &mut current);
+    flush_block(&mut blocks, &mut current);
+
+    blocks
+        .iter()
+        .map(|block| render_block(block))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+//
+// Private functions: tree walking
+//
+
+const fn is_block(name: &str) -> bool {
+    matches!(
+        name.as_bytes(),
+        b"div" | b"p" | b"blockquote" | b"center" | b"section"
+    )
+}
+
+fn walk_blocks(element: ElementRef, blocks: &mut Vec<Vec<Inline>>, current: &mut Vec<Inline>) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => push_text(current, text),
+            Node::Element(_) => {
+                let child_element = ElementRef::wrap(child).unwrap();
+
+                if is_block(child_element.value().name()) {
+                    flush_block(blocks, current);
+                    walk_blocks(child_element, blocks, current);
+                    flush_block(blocks, current);
+                } else {
+                    push_element(current, child_element);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn flush_block(blocks: &mut Vec<Vec<Inline>>, current: &mut Vec<Inline>) {
+    // Like pandoc's trimInlines: line breaks are kept, plain spaces are not
+    while current.first() == Some(&Inline::Space) {
+        current.remove(0);
+    }
+
+    while current.last() == Some(&Inline::Space) {
+        current.pop();
+    }
+
+    if !current.is_empty() {
+        blocks.push(std::mem::take(current));
+    }
+}
+
+fn push_children(out: &mut Vec<Inline>, element: ElementRef) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => push_text(out, text),
+            Node::Element(_) => push_element(out, ElementRef::wrap(child).unwrap()),
+            _ => {}
+        }
+    }
+}
+
+fn push_element(out: &mut Vec<Inline>, element: ElementRef) {
+    match element.value().name() {
+        "i" | "em" => push_wrapped(out, element, Inline::Emph),
+        "b" | "strong" => push_wrapped(out, element, Inline::Strong),
+        "sup" => push_wrapped(out, element, Inline::Superscript),
+        "br" => push(out, Inline::LineBreak),
+        "img" => {
+            let mut alt = Vec::new();
+            push_text(&mut alt, element.attr("alt").unwrap_or_default());
+
+            push(
+                out,
+                Inline::Image {
+                    alt,
+                    src: element.attr("src").unwrap_or_default().to_owned(),
+                    title: element.attr("title").unwrap_or_default().to_owned(),
+                },
+            );
+        }
+        "script" | "style" | "head" | "title" => {}
+        // Unknown tags (hw, pa, lang, c, etc.) are transparent, as in pandoc
+        _ => push_children(out, element),
+    }
+}
+
+// Like pandoc's extractSpaces: edge spaces are moved outside of the formatting
+fn push_wrapped(out: &mut Vec<Inline>, element: ElementRef, wrap: fn(Vec<Inline>) -> Inline) {
+    let mut inner = Vec::new();
+    push_children(&mut inner, element);
+
+    let leading = inner.first() == Some(&Inline::Space);
+    let trailing = inner.last() == Some(&Inline::Space);
+
+    if leading {
+        inner.remove(0);
+        push(out, Inline::Space);
+    }
+
+    if trailing && !inner.is_empty() {
+        inner.pop();
+    }
+
+    push(out, wrap(inner));
+
+    if trailing {
+        push(out, Inline::Space);
+    }
+}
+
+fn push_text(out: &mut Vec<Inline>, text: &str) {
+    let mut word = String::new();
+
+    for c in text.chars() {
+        if matches!(c, ' ' | '\t' | '\r' | '\n') {
+            if !word.is_empty() {
+                push(out, Inline::Str(std::mem::take(&mut word)));
+            }
+            push(out, Inline::Space);
+        } else {
+            word.push(c);
+        }
+    }
+
+    if !word.is_empty() {
+        push(out, Inline::Str(word));
+    }
+}
+
+// Mirrors the normalization in pandoc's Inlines monoid
+fn push(out: &mut Vec<Inline>, item: Inline) {
+    match (out.last_mut(), item) {
+        (Some(Inline::Space | Inline::LineBreak), Inline::Space) => {}
+        (Some(last @ Inline::Space), Inline::LineBreak) => *last = Inline::LineBreak,
+        (Some(Inline::Str(a)), Inline::Str(b)) => a.push_str(&b),
+        (Some(Inline::Emph(a)), Inline::Emph(b))
+        | (Some(Inline::Strong(a)), Inline::Strong(b))
+        | (Some(Inline::Superscript(a)), Inline::Superscript(b)) => {
+            for inline in b {
+                push(a, inline);
+            }
+        }
+        (_, item) => out.push(item),
+    }
+}
+
+//
+// Private functions: rendering
+//
+
+fn render_block(block: &[Inline]) -> String {
+    let mut rendered = String::new();
+
+    // A paragraph that opens with e.g. "1." would otherwise become an ordered list
+    let inlines = match block.split_first() {
+        Some((Inline::Str(text), rest))
+            if matches!(rest.first(), None | Some(Inline::Space)) && is_list_marker(text) =>
+        {
+            rendered.push_str(&text.replace('.', "\\."));
+            rest
+        }
+        _ => block,
+    };
+
+    render_inlines(&mut rendered, inlines);
+    rendered
+}
+
+fn render_inlines(out: &mut String, inlines: &[Inline]) {
+    for inline in inlines {
+        render_inline(out, inline);
+    }
+}
+
+fn render_inline(out: &mut String, inline: &Inline) {
+    match inline {
+        Inline::Str(text) => out.push_str(&escape_text(text)),
+        Inline::Space => out.push(' '),
+        Inline::LineBreak => out.push_str("  \n"),
+        Inline::Emph(inner) => {
+            out.push('*');
+            render_inlines(out, inner);
+            out.push('*');
+        }
+        Inline::Strong(inner) => {
+            out.push_str("**");
+            render_inlines(out, inner);
+            out.push_str("**");
+        }
+        Inline::Superscript(inner) => {
+            out.push_str("<sup>");
+            render_inlines(out, inner);
+            out.push_str("</sup>");
+        }
+        Inline::Image { alt, src, title } => {
+            out.push_str("![");
+            render_inlines(out, alt);
+            out.push_str("](");
+            out.push_str(&src.replace(' ', "%20"));
+
+            if !title.is_empty() {
+                out.push_str(" \"");
+                out.push_str(&title.replace('"', "\\\""));
+                out.push('"');
+            }
+
+            out.push(')');
+        }
+    }
+}
+
+fn is_list_marker(text: &str) -> bool {
+    text.strip_suffix('.')
+        .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn escape_text(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+
+    for c in input.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\\' | '`' | '*' | '_' | '[' | ']' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting() {
+        let html =
+            "<div><hw><pa>آب</pa> <i>āb</i></hw>, <b>Water</b>; <i>ab-i</i> <b><i>rū</i></b></div>";
+        assert_eq!(
+            html_to_markdown(html),
+            "آب *āb*, **Water**; *ab-i* ***rū***"
+        );
+    }
+
+    #[test]
+    fn spaces() {
+        assert_eq!(html_to_markdown("<div>  a \n\t b  </div>"), "a b");
+        assert_eq!(html_to_markdown("a<i> b </i>c"), "a *b* c");
+        assert_eq!(html_to_markdown("<i>a</i><i>b</i>"), "*ab*");
+        assert_eq!(html_to_markdown("a <br> b"), "a  \nb");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            html_to_markdown("a*b_c [d] \\ &lt;e&gt;"),
+            "a\\*b\\_c \\[d\\] \\\\ &lt;e&gt;"
+        );
+        assert_eq!(html_to_markdown("<p>1. one</p>"), "1\\. one");
+        assert_eq!(html_to_markdown("<p>1.5 one</p>"), "1.5 one");
+    }
+
+    #[test]
+    fn other_tags() {
+        assert_eq!(html_to_markdown("x<sup>2</sup>"), "x<sup>2</sup>");
+        assert_eq!(
+            html_to_markdown("<img src=\"a b.jpg\" alt=\"abjad\">"),
+            "![abjad](a%20b.jpg)"
+        );
+        assert_eq!(html_to_markdown("<p>a</p><p>b</p>"), "a\n\nb");
+    }
+}
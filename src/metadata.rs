@@ -0,0 +1,45 @@
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::error::Error;
+
+//
+// Public functions
+//
+
+pub fn get_metadata(conn: &Connection, key: &str) -> Result<Option<String>, Error> {
+    let mut stmt = conn.prepare_cached("SELECT value FROM metadata WHERE key = ?")?;
+    let value = stmt.query_row([key], |row| row.get(0)).optional()?;
+
+    Ok(value)
+}
+
+pub fn set_metadata(conn: &Connection, key: &str, value: &str) -> Result<(), Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)",
+        (key, value),
+    )?;
+
+    Ok(())
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_table;
+
+    #[test]
+    fn round_trip() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_table(&conn).unwrap();
+
+        assert_eq!(get_metadata(&conn, "x").unwrap(), None);
+
+        set_metadata(&conn, "x", "1").unwrap();
+        set_metadata(&conn, "x", "2").unwrap();
+        assert_eq!(get_metadata(&conn, "x").unwrap().as_deref(), Some("2"));
+    }
+}
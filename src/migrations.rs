@@ -0,0 +1,644 @@
+use rusqlite::Connection;
+
+use crate::Entry;
+use crate::abjad_values::{load_abjad_prefs, record_abjad_prefs};
+use crate::error::{Error, Location};
+use crate::langs::Lang;
+use crate::senses::set_senses;
+
+//
+// Type definitions
+//
+
+// A step's SQL is frozen here, and should not change once released: a DB at any version must
+// end up with the same schema as a fresh one
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: fn(&Connection) -> Result<(), Error>,
+    pub backfill: bool, // Adds columns derived from the entry, filled in once all steps are done
+}
+
+//
+// Constants
+//
+
+// Every step checks for what it creates, so that DBs built before `user_version` was recorded
+// (and those that were given columns or indexes by hand) can be brought up to date
+pub const MIGRATIONS: [Migration; 14] = [
+    Migration {
+        version: 1,
+        description: "entries table",
+        apply: create_entries_table,
+        backfill: false,
+    },
+    Migration {
+        version: 2,
+        description: "scrape progress",
+        apply: create_progress_table,
+        backfill: false,
+    },
+    Migration {
+        version: 3,
+        description: "images",
+        apply: create_images_table,
+        backfill: false,
+    },
+    Migration {
+        version: 4,
+        description: "entry positions and uids",
+        apply: add_uid_columns,
+        backfill: true,
+    },
+    Migration {
+        version: 5,
+        description: "senses and phrases",
+        apply: create_senses_tables,
+        backfill: true,
+    },
+    Migration {
+        version: 6,
+        description: "cross-references",
+        apply: create_cross_refs_table,
+        backfill: false,
+    },
+    Migration {
+        version: 7,
+        description: "metadata and abjad values",
+        apply: add_abjad,
+        backfill: true,
+    },
+    Migration {
+        version: 8,
+        description: "lookup indexes",
+        apply: create_indexes,
+        backfill: false,
+    },
+    Migration {
+        version: 9,
+        description: "full-text tables",
+        apply: create_fts_tables,
+        backfill: false,
+    },
+    Migration {
+        version: 10,
+        description: "full-text sync triggers",
+        apply: create_fts_triggers,
+        backfill: false,
+    },
+    Migration {
+        version: 11,
+        description: "normalized search headwords",
+        apply: add_search_column,
+        backfill: true,
+    },
+    Migration {
+        version: 12,
+        description: "folded Latin search headwords",
+        apply: add_latin_search_column,
+        backfill: true,
+    },
+    Migration {
+        version: 13,
+        description: "roots of Arabic words",
+        apply: add_root_column,
+        backfill: true,
+    },
+    Migration {
+        version: 14,
+        description: "normalized search phrases",
+        apply: add_phrase_search_column,
+        backfill: true,
+    },
+];
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+// The statements below are shared with `fts::fts_script`, so that a DB built from the script
+// matches a migrated one
+
+// From migration 4
+pub const UID_INDEX: &str = "CREATE UNIQUE INDEX IF NOT EXISTS idx_entries_uid ON entries(uid);\n";
+
+// From migration 8: the indexes that statements.sh used to create remotely
+pub const ENTRY_INDEXES: &str = "CREATE INDEX IF NOT EXISTS idx_page ON entries(page);
+CREATE INDEX IF NOT EXISTS idx_per ON entries(headword_persian);
+CREATE INDEX IF NOT EXISTS idx_abjad ON entries(abjad);
+";
+
+// From migration 9; each table takes its content from `entries`
+pub const FTS_TABLES: &str = "CREATE VIRTUAL TABLE IF NOT EXISTS ft_all
+    USING fts5(headword_full, definitions, content='entries', content_rowid='id');
+CREATE VIRTUAL TABLE IF NOT EXISTS ft_def
+    USING fts5(definitions, content='entries', content_rowid='id');
+CREATE VIRTUAL TABLE IF NOT EXISTS ft_hw
+    USING fts5(headword_full, content='entries', content_rowid='id');
+CREATE VIRTUAL TABLE IF NOT EXISTS ft_per
+    USING fts5(headword_persian, content='entries', content_rowid='id');
+";
+
+pub const FTS_REBUILD: &str = "INSERT INTO ft_all(ft_all) VALUES ('rebuild');
+INSERT INTO ft_def(ft_def) VALUES ('rebuild');
+INSERT INTO ft_hw(ft_hw) VALUES ('rebuild');
+INSERT INTO ft_per(ft_per) VALUES ('rebuild');
+";
+
+// From migration 10. External-content tables are updated by hand: a row is removed by
+// inserting a 'delete' command with its old values
+pub const FTS_TRIGGERS: &str =
+    "CREATE TRIGGER IF NOT EXISTS entries_fts_insert AFTER INSERT ON entries BEGIN
+    INSERT INTO ft_all(rowid, headword_full, definitions)
+        VALUES (new.id, new.headword_full, new.definitions);
+    INSERT INTO ft_def(rowid, definitions) VALUES (new.id, new.definitions);
+    INSERT INTO ft_hw(rowid, headword_full) VALUES (new.id, new.headword_full);
+    INSERT INTO ft_per(rowid, headword_persian) VALUES (new.id, new.headword_persian);
+END;
+CREATE TRIGGER IF NOT EXISTS entries_fts_delete AFTER DELETE ON entries BEGIN
+    INSERT INTO ft_all(ft_all, rowid, headword_full, definitions)
+        VALUES ('delete', old.id, old.headword_full, old.definitions);
+    INSERT INTO ft_def(ft_def, rowid, definitions) VALUES ('delete', old.id, old.definitions);
+    INSERT INTO ft_hw(ft_hw, rowid, headword_full) VALUES ('delete', old.id, old.headword_full);
+    INSERT INTO ft_per(ft_per, rowid, headword_persian)
+        VALUES ('delete', old.id, old.headword_persian);
+END;
+CREATE TRIGGER IF NOT EXISTS entries_fts_update
+    AFTER UPDATE OF headword_full, definitions, headword_persian ON entries BEGIN
+    INSERT INTO ft_all(ft_all, rowid, headword_full, definitions)
+        VALUES ('delete', old.id, old.headword_full, old.definitions);
+    INSERT INTO ft_def(ft_def, rowid, definitions) VALUES ('delete', old.id, old.definitions);
+    INSERT INTO ft_hw(ft_hw, rowid, headword_full) VALUES ('delete', old.id, old.headword_full);
+    INSERT INTO ft_per(ft_per, rowid, headword_persian)
+        VALUES ('delete', old.id, old.headword_persian);
+    INSERT INTO ft_all(rowid, headword_full, definitions)
+        VALUES (new.id, new.headword_full, new.definitions);
+    INSERT INTO ft_def(rowid, definitions) VALUES (new.id, new.definitions);
+    INSERT INTO ft_hw(rowid, headword_full) VALUES (new.id, new.headword_full);
+    INSERT INTO ft_per(rowid, headword_persian) VALUES (new.id, new.headword_persian);
+END;
+";
+
+//
+// Public functions
+//
+
+pub fn schema_version(conn: &Connection) -> Result<u32, Error> {
+    let version = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    Ok(version)
+}
+
+// Applies, in order and each in its own transaction, the migrations the DB has not had yet;
+// returns the number applied. Derived columns are filled in with the last of them
+pub fn migrate(conn: &Connection) -> Result<usize, Error> {
+    let current = schema_version(conn)?;
+
+    if current > SCHEMA_VERSION {
+        return Err(Error::Parse {
+            location: Location::default(),
+            message: format!(
+                "DB schema version {} is newer than this build supports ({})",
+                current, SCHEMA_VERSION
+            ),
+        });
+    }
+
+    let mut applied = 0;
+    let mut backfill = false;
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = conn.unchecked_transaction()?;
+        let failed = |e| match e {
+            Error::Database { location, source } => Error::Migration {
+                location,
+                version: migration.version,
+                description: migration.description,
+                source,
+            },
+            other => other,
+        };
+
+        (migration.apply)(&tx).map_err(failed)?;
+        backfill |= migration.backfill;
+
+        if backfill && migration.version == SCHEMA_VERSION {
+            fill_derived(&tx).map_err(failed)?;
+        }
+
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+
+        applied += 1;
+    }
+
+    Ok(applied)
+}
+
+//
+// Private functions
+//
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool, Error> {
+    let found = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        [table, column],
+        |row| row.get(0),
+    )?;
+
+    Ok(found)
+}
+
+// Computes the derived columns, senses and phrases with the current code, as `reparse` would,
+// rather than with whatever code a step was written alongside. Only the placeholder uids are
+// replaced; a uid once given is kept
+fn fill_derived(conn: &Connection) -> Result<(), Error> {
+    let prefs = load_abjad_prefs(conn)?;
+
+    let rows: Vec<(i64, Entry)> = conn
+        .prepare(
+            "SELECT id, page, position, uid, lang, headword_full, headword_persian,
+                headword_latin, definitions
+            FROM entries",
+        )?
+        .query_map([], |row| {
+            let lang: String = row.get(4)?;
+
+            Ok((
+                row.get(0)?,
+                Entry {
+                    page: row.get(1)?,
+                    position: row.get(2)?,
+                    uid: row.get(3)?,
+                    lang: lang.parse::<Lang>().unwrap_or_default(),
+                    headword_full: row.get(5)?,
+                    headword_persian: row.get(6)?,
+                    headword_latin: row.get(7)?,
+                    definitions: row.get(8)?,
+                    ..Entry::default()
+                },
+            ))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut stmt = conn.prepare(
+        "UPDATE entries SET
+            uid = ?1,
+            abjad = ?2,
+            headword_search = ?3,
+            headword_latin_search = ?4,
+            root = ?5
+        WHERE id = ?6",
+    )?;
+
+    for (id, mut entry) in rows {
+        let uid = entry.uid.clone();
+        entry.derive_fields(prefs);
+
+        if !uid.starts_with("pending-") {
+            entry.uid = uid;
+        }
+
+        stmt.execute((
+            &entry.uid,
+            entry.abjad,
+            &entry.headword_search,
+            &entry.headword_latin_search,
+            &entry.root,
+            id,
+        ))?;
+        set_senses(conn, id, &entry.senses)?;
+    }
+
+    record_abjad_prefs(conn, prefs)
+}
+
+fn create_entries_table(conn: &Connection) -> Result<(), Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS entries (
+            id INTEGER NOT NULL PRIMARY KEY,
+            page INTEGER NOT NULL,
+            raw_html TEXT NOT NULL,
+            lang TEXT NOT NULL,
+            headword_full TEXT NOT NULL,
+            headword_persian TEXT NOT NULL,
+            headword_latin TEXT NOT NULL,
+            definitions TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn create_progress_table(conn: &Connection) -> Result<(), Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS scrape_progress (
+            page INTEGER NOT NULL PRIMARY KEY,
+            state TEXT NOT NULL,
+            entry_count INTEGER,
+            error TEXT,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn create_images_table(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS images (
+            id INTEGER NOT NULL PRIMARY KEY,
+            entry_id INTEGER NOT NULL REFERENCES entries(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            src TEXT NOT NULL,
+            alt TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_images_entry ON images(entry_id);",
+    )?;
+
+    Ok(())
+}
+
+// Positions follow the order of the rows on each page, which is wrong for rows added by hand after
+// their page was scraped; `ids::assign_page_positions` corrects those from the page itself. Uids
+// are placeholders until the backfill
+fn add_uid_columns(conn: &Connection) -> Result<(), Error> {
+    if !has_column(conn, "entries", "uid")? {
+        conn.execute_batch(
+            "ALTER TABLE entries ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE entries ADD COLUMN uid TEXT NOT NULL DEFAULT '';
+            UPDATE entries SET
+                position = (
+                    SELECT COUNT(*) FROM entries AS e
+                    WHERE e.page = entries.page AND e.id <= entries.id
+                ),
+                uid = 'pending-' || id;",
+        )?;
+    }
+
+    conn.execute_batch(UID_INDEX)?;
+    Ok(())
+}
+
+fn create_senses_tables(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS senses (
+            id INTEGER NOT NULL PRIMARY KEY,
+            entry_id INTEGER NOT NULL REFERENCES entries(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            parent_position INTEGER,
+            number TEXT,
+            labels TEXT NOT NULL,
+            text TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_senses_entry ON senses(entry_id);
+        CREATE TABLE IF NOT EXISTS phrases (
+            id INTEGER NOT NULL PRIMARY KEY,
+            entry_id INTEGER NOT NULL REFERENCES entries(id) ON DELETE CASCADE,
+            sense_position INTEGER NOT NULL,
+            position INTEGER NOT NULL,
+            persian TEXT NOT NULL,
+            latin TEXT,
+            gloss TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_phrases_entry ON phrases(entry_id);
+        CREATE INDEX IF NOT EXISTS idx_phrases_persian ON phrases(persian);",
+    )?;
+
+    Ok(())
+}
+
+fn create_cross_refs_table(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS cross_refs (
+            id INTEGER NOT NULL PRIMARY KEY,
+            entry_id INTEGER NOT NULL REFERENCES entries(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            text TEXT NOT NULL,
+            target_persian TEXT,
+            target_latin TEXT,
+            target_id INTEGER REFERENCES entries(id) ON DELETE SET NULL,
+            status TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_cross_refs_entry ON cross_refs(entry_id);
+        CREATE INDEX IF NOT EXISTS idx_cross_refs_target ON cross_refs(target_id);",
+    )?;
+
+    Ok(())
+}
+
+fn add_abjad(conn: &Connection) -> Result<(), Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS metadata (
+            key TEXT NOT NULL PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    if !has_column(conn, "entries", "abjad")? {
+        conn.execute(
+            "ALTER TABLE entries ADD COLUMN abjad INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn create_indexes(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(ENTRY_INDEXES)?;
+    Ok(())
+}
+
+fn create_fts_tables(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(FTS_TABLES)?;
+    conn.execute_batch(FTS_REBUILD)?;
+    Ok(())
+}
+
+fn create_fts_triggers(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(FTS_TRIGGERS)?;
+    Ok(())
+}
+
+fn add_search_column(conn: &Connection) -> Result<(), Error> {
+    if !has_column(conn, "entries", "headword_search")? {
+        conn.execute(
+            "ALTER TABLE entries ADD COLUMN headword_search TEXT NOT NULL DEFAULT ''",
+            [],
+        )?;
+    }
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_search ON entries(headword_search)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn add_latin_search_column(conn: &Connection) -> Result<(), Error> {
+    if !has_column(conn, "entries", "headword_latin_search")? {
+        conn.execute(
+            "ALTER TABLE entries ADD COLUMN headword_latin_search TEXT NOT NULL DEFAULT ''",
+            [],
+        )?;
+    }
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_latin_search ON entries(headword_latin_search)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn add_root_column(conn: &Connection) -> Result<(), Error> {
+    if !has_column(conn, "entries", "root")? {
+        conn.execute("ALTER TABLE entries ADD COLUMN root TEXT", [])?;
+    }
+
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_root ON entries(root)", [])?;
+
+    Ok(())
+}
+
+fn add_phrase_search_column(conn: &Connection) -> Result<(), Error> {
+    if !has_column(conn, "phrases", "persian_search")? {
+        conn.execute(
+            "ALTER TABLE phrases ADD COLUMN persian_search TEXT NOT NULL DEFAULT ''",
+            [],
+        )?;
+    }
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_phrases_search ON phrases(persian_search)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_ordered() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, u32::try_from(i + 1).unwrap());
+        }
+    }
+
+    #[test]
+    fn fresh_and_repeated() {
+        let conn = Connection::open_in_memory().unwrap();
+
+        assert_eq!(migrate(&conn).unwrap(), MIGRATIONS.len());
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert_eq!(migrate(&conn).unwrap(), 0);
+
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        assert!(migrate(&conn).is_err());
+    }
+
+    #[test]
+    fn failure() {
+        // A uid column added by hand, with duplicate values
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE entries (
+                id INTEGER NOT NULL PRIMARY KEY,
+                page INTEGER NOT NULL,
+                raw_html TEXT NOT NULL,
+                lang TEXT NOT NULL,
+                headword_full TEXT NOT NULL,
+                headword_persian TEXT NOT NULL,
+                headword_latin TEXT NOT NULL,
+                definitions TEXT NOT NULL,
+                uid TEXT NOT NULL DEFAULT ''
+            );
+            INSERT INTO entries VALUES (1, 3, '', 'Persian', 'آب āb', 'آب', 'āb', 'Water', '');
+            INSERT INTO entries VALUES (2, 3, '', 'Persian', 'آبا ābā', 'آبا', 'ābā', 'Father', '');",
+        )
+        .unwrap();
+
+        let error = migrate(&conn).unwrap_err();
+        assert!(matches!(error, Error::Migration { version: 4, .. }));
+        assert_eq!(schema_version(&conn).unwrap(), 3);
+    }
+
+    #[test]
+    fn legacy() {
+        // As left by the original scraper, with the abjad column added by hand
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE entries (
+                id INTEGER NOT NULL PRIMARY KEY,
+                page INTEGER NOT NULL,
+                raw_html TEXT NOT NULL,
+                lang TEXT NOT NULL,
+                headword_full TEXT NOT NULL,
+                headword_persian TEXT NOT NULL,
+                headword_latin TEXT NOT NULL,
+                definitions TEXT NOT NULL,
+                abjad INTEGER
+            );
+            INSERT INTO entries VALUES (
+                1, 3, '', 'Persian', 'آب āb', 'آب', 'āb', 'Water; آب رو *āb-rū*, Honour', NULL
+            );
+            CREATE INDEX idx_page ON entries(page);",
+        )
+        .unwrap();
+
+        migrate(&conn).unwrap();
+
+        let (uid, abjad): (String, u32) = conn
+            .query_row("SELECT uid, abjad FROM entries", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert!(uid.starts_with("0003-001-"));
+        assert_eq!(abjad, 3);
+
+        let (search, latin_search): (String, String) = conn
+            .query_row(
+                "SELECT headword_search, headword_latin_search FROM entries",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(search, "اب");
+        assert_eq!(latin_search, "ab");
+
+        let (senses, phrases): (i64, i64) = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM senses), (SELECT COUNT(*) FROM phrases)",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((senses, phrases), (2, 1));
+
+        let phrase_search: String = conn
+            .query_row("SELECT persian_search FROM phrases", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(phrase_search, "اب رو");
+
+        let matches: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM ft_def WHERE ft_def MATCH 'water'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(matches, 1);
+    }
+}
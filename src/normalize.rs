@@ -0,0 +1,274 @@
+use rusqlite::Connection;
+
+use crate::clean_simple;
+use crate::error::Error;
+
+//
+// Type definitions
+//
+
+// An entry found by its transliteration
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Default)]
+pub struct LatinMatch {
+    pub id: i64,
+    pub headword_latin: String,
+    pub exact: bool, // Diacritics and all, rather than only once folded
+}
+
+//
+// Public functions
+//
+
+// Folds the spellings that users type interchangeably into one form, for storage in the
+// `headword_search` and `phrases.persian_search` columns and for queries against them
+#[must_use]
+pub fn normalize_for_search(input: &str) -> String {
+    let cleaned = clean_simple(input);
+    let mut folded = String::with_capacity(cleaned.len());
+
+    for c in cleaned.chars() {
+        match c {
+            // Diacritics (harakat, tanwin, shadda, sukun, superscript alef, etc.), tatweel,
+            // joiners, direction marks, and hamza on its own
+            '\u{064B}'..='\u{065F}'
+            | '\u{0670}'
+            | '\u{06D6}'..='\u{06ED}'
+            | '\u{0640}'
+            | '\u{200D}'
+            | '\u{200E}'
+            | '\u{200F}'
+            | '\u{0621}'
+            | '\u{0674}' => {}
+
+            // Hamza carriers, alif with madda or wasla, and the Arabic letter shapes that Persian
+            // spells differently, to the bare Persian letter
+            '\u{0622}' | '\u{0623}' | '\u{0625}' | '\u{0671}' | '\u{0672}' | '\u{0673}' => {
+                folded.push('\u{0627}');
+            }
+            '\u{0624}' => folded.push('\u{0648}'),
+            '\u{0626}' | '\u{06D0}' | '\u{064A}' | '\u{0649}' => folded.push('\u{06CC}'),
+            '\u{0643}' | '\u{06AA}' => folded.push('\u{06A9}'),
+            '\u{0629}' | '\u{06C0}' | '\u{06C1}' | '\u{06D5}' => folded.push('\u{0647}'),
+
+            // ZWNJ is used inconsistently, and often typed as a space
+            '\u{200C}' => folded.push(' '),
+
+            _ => folded.push(c),
+        }
+    }
+
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Reduces a transliteration (anything in `OTHER_ALLOWED`, plus a few spellings that users type
+// for the digraphs) to lowercase ASCII letters and digits, e.g., "ḵẖāna" and "khana" alike
+#[must_use]
+pub fn fold_latin(input: &str) -> String {
+    let mut folded = String::with_capacity(input.len());
+
+    for c in input.chars() {
+        match c {
+            // Never found in Steingass's headwords, so it can only stand for "kh"
+            'x' | 'X' => folded.push_str("kh"),
+
+            'a'..='z' | '0'..='9' => folded.push(c),
+            'A'..='Z' => folded.push(c.to_ascii_lowercase()),
+
+            // Long and accented vowels
+            '\u{00E1}' | '\u{0101}' => folded.push('a'),
+            '\u{0113}' | '\u{0115}' => folded.push('e'),
+            '\u{00EE}' | '\u{012B}' => folded.push('i'),
+            '\u{014D}' => folded.push('o'),
+            '\u{00FC}' | '\u{016B}' => folded.push('u'),
+
+            // Consonants with a dot or line below (underlined digraphs, e.g., "ḵẖ", fold to their
+            // two letters)
+            '\u{1E25}' | '\u{1E96}' => folded.push('h'),
+            '\u{1E35}' => folded.push('k'),
+            '\u{1E43}' => folded.push('m'),
+            '\u{1E47}' => folded.push('n'),
+            '\u{1E5B}' => folded.push('r'),
+            '\u{1E63}' => folded.push('s'),
+            '\u{1E6D}' => folded.push('t'),
+            '\u{1E89}' => folded.push('w'),
+            '\u{1E93}' | '\u{1E95}' => folded.push('z'),
+
+            // Single letters that other schemes use for the digraphs (and "x", above)
+            '\u{0161}' => folded.push_str("sh"),
+            '\u{017E}' => folded.push_str("zh"),
+            '\u{0121}' | '\u{011F}' => folded.push_str("gh"),
+            '\u{010D}' => folded.push_str("ch"),
+
+            // Everything else: ayn and hamza (quote marks), combining marks below, stress
+            // marks, Markdown emphasis, spaces, hyphens and other punctuation
+            _ => {}
+        }
+    }
+
+    folded
+}
+
+// Entries whose transliteration matches the query once both are folded; those that match it
+// exactly (apart from Markdown emphasis) come first
+pub fn find_latin(conn: &Connection, query: &str) -> Result<Vec<LatinMatch>, Error> {
+    let folded = fold_latin(query);
+
+    if folded.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Latin headwords are stored in their Markdown (italic) form
+    let plain = clean_simple(query.trim_matches('*'));
+    let starred = format!("*{}*", plain);
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, headword_latin, (headword_latin = ?2 OR headword_latin = ?3) AS exact
+        FROM entries
+        WHERE headword_latin_search = ?1
+        ORDER BY exact DESC, id",
+    )?;
+
+    let matches = stmt
+        .query_map((&folded, &plain, &starred), |row| {
+            Ok(LatinMatch {
+                id: row.get(0)?,
+                headword_latin: row.get(1)?,
+                exact: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(matches)
+}
+
+// IDs of the entries whose Persian headword matches the query once both are normalized
+pub fn find_normalized(conn: &Connection, query: &str) -> Result<Vec<i64>, Error> {
+    let mut stmt =
+        conn.prepare_cached("SELECT id FROM entries WHERE headword_search = ? ORDER BY id")?;
+
+    let ids = stmt
+        .query_map([normalize_for_search(query)], |row| row.get(0))?
+        .collect::<Result<Vec<i64>, _>>()?;
+
+    Ok(ids)
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::charsets::OTHER_ALLOWED;
+    use crate::{ensure_table, insert_row, parse_entry, test_db};
+
+    #[test]
+    fn folding() {
+        // Arabic kaf and yeh
+        assert_eq!(normalize_for_search("كتاب"), "کتاب");
+        assert_eq!(normalize_for_search("كي"), "کی");
+
+        // Harakat, fathatayn, shadda and superscript alef
+        assert_eq!(normalize_for_search("مُحَمَّد"), "محمد");
+        assert_eq!(normalize_for_search("اتفاقاً"), "اتفاقا");
+        assert_eq!(normalize_for_search("رحمٰن"), "رحمن");
+
+        // Hamza carriers and madda
+        assert_eq!(normalize_for_search("آب"), "اب");
+        assert_eq!(normalize_for_search("أمر"), "امر");
+        assert_eq!(normalize_for_search("مؤمن"), "مومن");
+        assert_eq!(normalize_for_search("مسئله"), "مسیله");
+        assert_eq!(normalize_for_search("ماء"), "ما");
+
+        // ZWNJ and spacing
+        assert_eq!(normalize_for_search("آتش‌آلود"), "اتش الود");
+        assert_eq!(normalize_for_search("  آتش   آلود "), "اتش الود");
+    }
+
+    #[test]
+    fn latin_folding() {
+        assert_eq!(fold_latin("*ḵẖāna*"), "khana");
+        assert_eq!(fold_latin("ṣāḥib"), "sahib");
+        assert_eq!(fold_latin("s̲h̲āh"), "shah");
+        assert_eq!(fold_latin("ʿilm"), "ilm");
+        assert_eq!(fold_latin("‘ilm"), "ilm");
+        assert_eq!(fold_latin("āb-rū"), "abru");
+        assert_eq!(fold_latin("šāh"), "shah");
+        assert_eq!(fold_latin("xāna"), "khana");
+
+        for c in OTHER_ALLOWED {
+            let c = char::from_u32(c).unwrap();
+            let folded = fold_latin(&c.to_string());
+            assert!(
+                folded.chars().all(|f| f.is_ascii_alphanumeric()),
+                "Unfolded char: U+{:04X}",
+                c as u32
+            );
+
+            // Only marks, punctuation and modifier letters (ʿ, ʾ, stress) may fold away; a
+            // letter that did would be unsearchable
+            let modifier = ('\u{02B0}'..='\u{02FF}').contains(&c);
+            assert!(
+                !c.is_alphabetic() || modifier || !folded.is_empty(),
+                "Letter folded to nothing: U+{:04X}",
+                c as u32
+            );
+        }
+    }
+
+    #[test]
+    fn latin_lookup() {
+        let conn = test_db(&[
+            (
+                470,
+                "<div><hw><pa>خانه</pa> <i>ḵẖāna</i></hw>, A house</div>",
+            ),
+            (
+                470,
+                "<div><hw><pa>خانا</pa> <i>ḵẖānā</i></hw>, O house!</div>",
+            ),
+        ]);
+
+        let folded: Vec<String> = conn
+            .prepare("SELECT headword_latin_search FROM entries")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(folded, vec!["khana", "khana"]);
+
+        let found = find_latin(&conn, "ḵẖānā").unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].id, found[0].exact), (2, true));
+        assert_eq!((found[1].id, found[1].exact), (1, false));
+
+        let found = find_latin(&conn, "khana").unwrap();
+        assert!(found.iter().all(|m| !m.exact));
+        assert_eq!(found.len(), 2);
+
+        assert!(find_latin(&conn, "sahib").unwrap().is_empty());
+        assert!(find_latin(&conn, "‘").unwrap().is_empty());
+    }
+
+    #[test]
+    fn lookup() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_table(&conn).unwrap();
+
+        let entry = parse_entry(
+            14,
+            2,
+            "<div><hw><pa>آتش‌آلود</pa> <i>ātish-ālūd</i></hw>, Suffused with fire.</div>"
+                .to_owned(),
+        )
+        .unwrap();
+        assert_eq!(entry.headword_search, "اتش الود");
+
+        let id = insert_row(&conn, entry).unwrap();
+
+        assert_eq!(find_normalized(&conn, "اتش آلود").unwrap(), vec![id]);
+        assert!(find_normalized(&conn, "آتش").unwrap().is_empty());
+    }
+}
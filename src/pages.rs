@@ -0,0 +1,171 @@
+use reqwest::blocking::get;
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::error::{Error, Location};
+use crate::{PREFIX, now};
+
+//
+// Type definitions
+//
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+pub struct StoredPage {
+    pub page: u16,
+    pub fetched_at: u64,
+    pub status: u16,
+    pub html: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+pub enum CacheMode {
+    // Use the store; fetch (and store) only missing or failed pages
+    #[default]
+    ReadThrough,
+    // Always fetch, replacing whatever is in the store
+    Refresh,
+    // Never touch the network
+    Offline,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum PageSource {
+    Store,
+    Network,
+}
+
+impl StoredPage {
+    #[must_use]
+    pub const fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+}
+
+//
+// Public functions
+//
+
+pub fn ensure_pages_table(conn: &Connection) -> Result<(), Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pages (
+            page INTEGER NOT NULL PRIMARY KEY,
+            fetched_at INTEGER NOT NULL,
+            status INTEGER NOT NULL,
+            html TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+pub fn fetch_page(page: u16) -> Result<StoredPage, Error> {
+    let url = format!("{}{}", PREFIX, page);
+    let at = |e: reqwest::Error| Error::from(e).at(Location::page(page));
+
+    let response = get(url).map_err(at)?;
+    let status = response.status().as_u16();
+    let html = response.text().map_err(at)?;
+
+    Ok(StoredPage {
+        page,
+        fetched_at: now(),
+        status,
+        html,
+    })
+}
+
+pub fn store_page(conn: &Connection, stored: &StoredPage) -> Result<(), Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO pages (page, fetched_at, status, html) VALUES (?1, ?2, ?3, ?4)",
+        (stored.page, stored.fetched_at, stored.status, &stored.html),
+    )?;
+
+    Ok(())
+}
+
+pub fn load_page(conn: &Connection, page: u16) -> Result<Option<StoredPage>, Error> {
+    let mut stmt = conn.prepare("SELECT fetched_at, status, html FROM pages WHERE page = ?")?;
+    let stored = stmt
+        .query_row([page], |row| {
+            Ok(StoredPage {
+                page,
+                fetched_at: row.get(0)?,
+                status: row.get(1)?,
+                html: row.get(2)?,
+            })
+        })
+        .optional()?;
+
+    Ok(stored)
+}
+
+pub fn get_page(
+    conn: &Connection,
+    page: u16,
+    mode: CacheMode,
+) -> Result<(StoredPage, PageSource), Error> {
+    if mode != CacheMode::Refresh {
+        match load_page(conn, page)? {
+            Some(stored) if stored.is_success() || mode == CacheMode::Offline => {
+                return Ok((stored, PageSource::Store));
+            }
+            None if mode == CacheMode::Offline => {
+                return Err(Error::NotInStore {
+                    location: Location::page(page),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let fetched = fetch_page(page)?;
+    store_page(conn, &fetched)?;
+
+    Ok((fetched, PageSource::Network))
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(page: u16, status: u16) -> StoredPage {
+        StoredPage {
+            page,
+            fetched_at: 1_700_000_000,
+            status,
+            html: format!("<html><body>p. {}</body></html>", page),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_pages_table(&conn).unwrap();
+
+        assert_eq!(load_page(&conn, 5).unwrap(), None);
+
+        store_page(&conn, &sample(5, 200)).unwrap();
+        assert_eq!(load_page(&conn, 5).unwrap(), Some(sample(5, 200)));
+
+        // Re-storing a page replaces it
+        store_page(&conn, &sample(5, 503)).unwrap();
+        assert_eq!(load_page(&conn, 5).unwrap().unwrap().status, 503);
+    }
+
+    #[test]
+    fn offline() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_pages_table(&conn).unwrap();
+        store_page(&conn, &sample(7, 200)).unwrap();
+
+        let (stored, source) = get_page(&conn, 7, CacheMode::Offline).unwrap();
+        assert_eq!(stored, sample(7, 200));
+        assert_eq!(source, PageSource::Store);
+
+        assert!(get_page(&conn, 8, CacheMode::Offline).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use regex::Regex;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::clean_simple;
+use crate::error::Error;
+use crate::normalize::normalize_for_search;
+
+//
+// Type definitions
+//
+
+// A run-in compound or idiom within a sense: Persian or Arabic script, usually followed by an
+// italic transliteration and an English gloss
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Default, Serialize, Deserialize)]
+pub struct Phrase {
+    pub persian: String,
+    pub latin: Option<String>,
+    pub gloss: Option<String>,
+}
+
+// A phrase found by lookup, with the entry it belongs to
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Default)]
+pub struct PhraseMatch {
+    pub entry_id: i64,
+    pub entry_uid: String,
+    pub entry_headword: String,
+    pub phrase: Phrase,
+}
+
+//
+// Public functions
+//
+
+// Finds the phrases in the Markdown text of a sense; the gloss of each runs up to the next
+// transliterated phrase, so that citations in script stay part of it
+#[must_use]
+pub fn extract_phrases(text: &str) -> Vec<Phrase> {
+    let re_phrase =
+        Regex::new(r"(\p{Arabic}(?:[\p{Arabic}\u{200C} ]*\p{Arabic})?)(?:\s*\*([^*]+)\*)?")
+            .unwrap();
+
+    let matches: Vec<_> = re_phrase.captures_iter(text).collect();
+    let mut phrases = Vec::new();
+
+    for (i, caps) in matches.iter().enumerate() {
+        let latin = caps.get(2).map(|m| clean_simple(m.as_str()));
+
+        // Without a transliteration, the script is a citation rather than a sub-entry
+        let gloss = latin.as_ref().and_then(|_| {
+            let start = caps.get(0).unwrap().end();
+            let end = matches[i + 1..]
+                .iter()
+                .find(|next| next.get(2).is_some())
+                .map_or(text.len(), |next| next.get(0).unwrap().start());
+
+            let gloss = text[start..end]
+                .trim_start_matches([' ', ',', ':'])
+                .trim_end_matches([' ', ',', ';', ':']);
+
+            (!gloss.is_empty()).then(|| gloss.to_owned())
+        });
+
+        phrases.push(Phrase {
+            persian: clean_simple(&caps[1]),
+            latin,
+            gloss,
+        });
+    }
+
+    phrases
+}
+
+// Looks up sub-entries the way `Query::Headword` looks up top-level entries, by their
+// normalized form; a query that normalizes to nothing matches nothing
+pub fn lookup_phrases(conn: &Connection, persian: &str) -> Result<Vec<PhraseMatch>, Error> {
+    let normalized = normalize_for_search(persian);
+    if normalized.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.uid, e.headword_full, p.persian, p.latin, p.gloss
+        FROM phrases p
+        JOIN entries e ON e.id = p.entry_id
+        WHERE p.persian_search = ? AND p.latin IS NOT NULL
+        ORDER BY e.id, p.sense_position, p.position",
+    )?;
+
+    let matches = stmt
+        .query_map([normalized], |row| {
+            Ok(PhraseMatch {
+                entry_id: row.get(0)?,
+                entry_uid: row.get(1)?,
+                entry_headword: row.get(2)?,
+                phrase: Phrase {
+                    persian: row.get(3)?,
+                    latin: row.get(4)?,
+                    gloss: row.get(5)?,
+                },
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(matches)
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ensure_table, insert_row, parse_entry};
+
+    #[test]
+    fn extraction() {
+        let phrases = extract_phrases(
+            "آب رو *āb-rū*, Honour, reputation, آب دادن *āb dādan*: To water (cf. ماء)",
+        );
+
+        assert_eq!(
+            phrases,
+            vec![
+                Phrase {
+                    persian: "آب رو".to_owned(),
+                    latin: Some("āb-rū".to_owned()),
+                    gloss: Some("Honour, reputation".to_owned()),
+                },
+                Phrase {
+                    persian: "آب دادن".to_owned(),
+                    latin: Some("āb dādan".to_owned()),
+                    gloss: Some("To water (cf. ماء)".to_owned()),
+                },
+                Phrase {
+                    persian: "ماء".to_owned(),
+                    latin: None,
+                    gloss: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn lookup() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_table(&conn).unwrap();
+
+        let entry = parse_entry(
+            3,
+            1,
+            "<div><hw><pa>آب</pa> <i>āb</i></hw>, s. Water; آب رو <i>āb-rū</i>, Honour; \
+            آب کشیدن <i>āb kashīdan</i>, To draw water</div>"
+                .to_owned(),
+        )
+        .unwrap();
+        let id = insert_row(&conn, entry.clone()).unwrap();
+
+        let matches = lookup_phrases(&conn, "آب رو").unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].entry_id, id);
+        assert_eq!(matches[0].entry_uid, entry.uid);
+        assert_eq!(matches[0].phrase.gloss.as_deref(), Some("Honour"));
+
+        // Arabic letter shapes, harakat and ZWNJ, as users type them
+        assert_eq!(lookup_phrases(&conn, "آبِ\u{200C}رو").unwrap(), matches);
+        assert_eq!(lookup_phrases(&conn, "أب رو").unwrap(), matches);
+
+        let matches = lookup_phrases(&conn, "آب كشيدن").unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].phrase.persian, "آب کشیدن");
+
+        assert!(lookup_phrases(&conn, "آتش").unwrap().is_empty());
+        assert!(lookup_phrases(&conn, "\u{064E}").unwrap().is_empty());
+    }
+}
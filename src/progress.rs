@@ -0,0 +1,229 @@
+use std::str::FromStr;
+
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::error::{Error, Location};
+use crate::now;
+
+//
+// Type definitions
+//
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Default)]
+pub enum PageState {
+    #[default]
+    Pending,
+    Fetched,
+    Parsed,
+    Verified,
+    Failed,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+pub struct PageProgress {
+    pub page: u16,
+    pub state: PageState,
+    pub entry_count: Option<usize>,
+    pub error: Option<String>,
+    pub attempts: u32,
+    pub updated_at: u64,
+}
+
+impl PageState {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Fetched => "fetched",
+            Self::Parsed => "parsed",
+            Self::Verified => "verified",
+            Self::Failed => "failed",
+        }
+    }
+
+    // Pages in these states need no further scraping
+    #[must_use]
+    pub const fn is_done(self) -> bool {
+        matches!(self, Self::Parsed | Self::Verified)
+    }
+}
+
+impl FromStr for PageState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "fetched" => Ok(Self::Fetched),
+            "parsed" => Ok(Self::Parsed),
+            "verified" => Ok(Self::Verified),
+            "failed" => Ok(Self::Failed),
+            _ => Err(Error::Parse {
+                location: Location::default(),
+                message: format!("Unrecognized page state: {}", s),
+            }),
+        }
+    }
+}
+
+//
+// Public functions
+//
+
+pub fn load_progress(conn: &Connection, page: u16) -> Result<Option<PageProgress>, Error> {
+    let mut stmt = conn.prepare(
+        "SELECT state, entry_count, error, attempts, updated_at
+        FROM scrape_progress
+        WHERE page = ?",
+    )?;
+
+    let row = stmt
+        .query_row([page], |row| {
+            let state: String = row.get(0)?;
+
+            Ok((
+                state,
+                PageProgress {
+                    page,
+                    entry_count: row.get(1)?,
+                    error: row.get(2)?,
+                    attempts: row.get(3)?,
+                    updated_at: row.get(4)?,
+                    ..PageProgress::default()
+                },
+            ))
+        })
+        .optional()?;
+
+    let Some((state, mut progress)) = row else {
+        return Ok(None);
+    };
+    progress.state = PageState::from_str(&state).map_err(|e| e.at(Location::page(page)))?;
+
+    Ok(Some(progress))
+}
+
+// Marks the start of a new attempt on a page
+pub fn begin_attempt(conn: &Connection, page: u16) -> Result<(), Error> {
+    conn.execute(
+        "INSERT INTO scrape_progress (page, state, attempts, updated_at)
+        VALUES (?1, ?2, 1, ?3)
+        ON CONFLICT (page) DO UPDATE SET
+            state = excluded.state,
+            error = NULL,
+            attempts = attempts + 1,
+            updated_at = excluded.updated_at",
+        (page, PageState::Pending.as_str(), now()),
+    )?;
+
+    Ok(())
+}
+
+pub fn set_state(
+    conn: &Connection,
+    page: u16,
+    state: PageState,
+    entry_count: Option<usize>,
+    error: Option<&str>,
+) -> Result<(), Error> {
+    conn.execute(
+        "INSERT INTO scrape_progress (page, state, entry_count, error, updated_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT (page) DO UPDATE SET
+            state = excluded.state,
+            entry_count = COALESCE(excluded.entry_count, entry_count),
+            error = excluded.error,
+            updated_at = excluded.updated_at",
+        (page, state.as_str(), entry_count, error, now()),
+    )?;
+
+    Ok(())
+}
+
+pub fn count_by_state(conn: &Connection) -> Result<Vec<(PageState, usize)>, Error> {
+    // The first page in each state locates any that is not recognized
+    let mut stmt = conn.prepare(
+        "SELECT state, COUNT(*), MIN(page) FROM scrape_progress GROUP BY state ORDER BY state",
+    )?;
+
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<Vec<(String, usize, u16)>, _>>()?;
+
+    rows.into_iter()
+        .map(|(state, count, page)| {
+            let state = PageState::from_str(&state).map_err(|e| e.at(Location::page(page)))?;
+            Ok((state, count))
+        })
+        .collect()
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_table;
+
+    #[test]
+    fn attempts() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_table(&conn).unwrap();
+
+        assert_eq!(load_progress(&conn, 9).unwrap(), None);
+
+        begin_attempt(&conn, 9).unwrap();
+        set_state(&conn, 9, PageState::Fetched, Some(14), None).unwrap();
+        set_state(&conn, 9, PageState::Failed, None, Some("Missing hw")).unwrap();
+
+        let progress = load_progress(&conn, 9).unwrap().unwrap();
+        assert_eq!(progress.state, PageState::Failed);
+        assert_eq!(progress.entry_count, Some(14));
+        assert_eq!(progress.error.as_deref(), Some("Missing hw"));
+        assert_eq!(progress.attempts, 1);
+
+        begin_attempt(&conn, 9).unwrap();
+        set_state(&conn, 9, PageState::Parsed, Some(14), None).unwrap();
+
+        let progress = load_progress(&conn, 9).unwrap().unwrap();
+        assert!(progress.state.is_done());
+        assert_eq!(progress.error, None);
+        assert_eq!(progress.attempts, 2);
+
+        assert_eq!(count_by_state(&conn).unwrap(), vec![(PageState::Parsed, 1)]);
+    }
+
+    #[test]
+    fn state_strings() {
+        for state in [
+            PageState::Pending,
+            PageState::Fetched,
+            PageState::Parsed,
+            PageState::Verified,
+            PageState::Failed,
+        ] {
+            assert_eq!(PageState::from_str(state.as_str()).unwrap(), state);
+        }
+
+        assert!(PageState::from_str("done").is_err());
+    }
+
+    #[test]
+    fn unknown_state() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_table(&conn).unwrap();
+
+        set_state(&conn, 9, PageState::Parsed, Some(14), None).unwrap();
+        conn.execute("UPDATE scrape_progress SET state = 'done'", [])
+            .unwrap();
+
+        let error = load_progress(&conn, 9).unwrap_err();
+        assert!(matches!(error, Error::Parse { .. }));
+        assert_eq!(error.location(), Location::page(9));
+
+        let error = count_by_state(&conn).unwrap_err();
+        assert_eq!(error.location(), Location::page(9));
+    }
+}
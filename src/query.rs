@@ -0,0 +1,313 @@
+use rusqlite::Connection;
+use rusqlite::types::Value;
+
+use crate::error::Error;
+use crate::images::load_images;
+use crate::langs::Lang;
+use crate::normalize::{fold_latin, normalize_for_search};
+use crate::roots::normalize_root;
+use crate::senses::load_senses;
+use crate::{ENTRY_COLUMNS, Entry, clean_simple, read_entry};
+
+//
+// Type definitions
+//
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub enum Script {
+    Persian, // Matched against `headword_search`
+    Latin,   // Matched against `headword_latin_search`
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+pub enum Query {
+    // Normalized (or folded) headword, in full or as a prefix
+    Headword {
+        script: Script,
+        text: String,
+        prefix: bool,
+    },
+    // Normalized sub-entry (compound or idiom), in full or as a prefix; matches the entries
+    // whose senses contain it
+    Phrase {
+        text: String,
+        prefix: bool,
+    },
+    Definitions(String), // FTS5 query syntax, against `ft_def`
+    Root(String),        // In Arabic script, with or without separators
+    Page(u16),
+    Abjad(u32),
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+pub struct Search {
+    pub query: Query,
+    pub lang: Option<Lang>,
+    pub limit: Option<usize>,
+}
+
+//
+// Implementations
+//
+
+impl Search {
+    #[must_use]
+    pub const fn new(query: Query) -> Self {
+        Self {
+            query,
+            lang: None,
+            limit: None,
+        }
+    }
+}
+
+//
+// Public functions
+//
+
+// Results come in dictionary order, except that Latin headwords matching the query exactly
+// (diacritics and all) come before those that match only once folded; a headword that
+// normalizes to nothing matches nothing
+pub fn search(conn: &Connection, search: &Search) -> Result<Vec<(i64, Entry)>, Error> {
+    let (condition, order, mut params) = match &search.query {
+        Query::Headword {
+            script: Script::Persian,
+            text,
+            prefix,
+        } => {
+            let normalized = normalize_for_search(text);
+            if normalized.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let (condition, params) = headword_condition("headword_search", normalized, *prefix);
+            (condition, "id", params)
+        }
+        Query::Headword {
+            script: Script::Latin,
+            text,
+            prefix,
+        } => {
+            let folded = fold_latin(text);
+            if folded.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let (condition, mut params) =
+                headword_condition("headword_latin_search", folded, *prefix);
+
+            // Latin headwords are stored in their Markdown (italic) form
+            let plain = clean_simple(text.trim_matches('*'));
+            params.push(Value::Text(format!("*{}*", plain)));
+            params.push(Value::Text(plain));
+
+            (
+                condition,
+                "(headword_latin = ?2 OR headword_latin = ?3) DESC, id",
+                params,
+            )
+        }
+        Query::Phrase { text, prefix } => {
+            let normalized = normalize_for_search(text);
+            if normalized.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            // As in `lookup_phrases`, script without a transliteration is only a citation
+            let (condition, params) = headword_condition("persian_search", normalized, *prefix);
+            (
+                format!(
+                    "id IN (SELECT entry_id FROM phrases WHERE {} AND latin IS NOT NULL)",
+                    condition
+                ),
+                "id",
+                params,
+            )
+        }
+        Query::Definitions(text) => (
+            "id IN (SELECT rowid FROM ft_def WHERE ft_def MATCH ?1)".to_owned(),
+            "id",
+            vec![Value::Text(text.clone())],
+        ),
+        Query::Root(root) => (
+            "root = ?1".to_owned(),
+            "id",
+            vec![Value::Text(normalize_root(root))],
+        ),
+        Query::Page(page) => (
+            "page = ?1".to_owned(),
+            "id",
+            vec![Value::Integer(i64::from(*page))],
+        ),
+        Query::Abjad(abjad) => (
+            "abjad = ?1".to_owned(),
+            "id",
+            vec![Value::Integer(i64::from(*abjad))],
+        ),
+    };
+
+    let lang_param = params.len() + 1;
+    let limit_param = params.len() + 2;
+
+    params.push(
+        search
+            .lang
+            .map_or(Value::Null, |lang| Value::Text(lang.as_str().to_owned())),
+    );
+    params.push(Value::Integer(
+        search
+            .limit
+            .map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX)),
+    ));
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM entries
+        WHERE {} AND (?{} IS NULL OR lang = ?{})
+        ORDER BY {}
+        LIMIT ?{}",
+        ENTRY_COLUMNS, condition, lang_param, lang_param, order, limit_param
+    ))?;
+
+    let mut entries = stmt
+        .query_map(rusqlite::params_from_iter(params), |row| {
+            Ok((row.get(0)?, read_entry(row)?))
+        })?
+        .collect::<Result<Vec<(i64, Entry)>, _>>()?;
+
+    for (id, entry) in &mut entries {
+        entry.images = load_images(conn, *id)?;
+        entry.senses = load_senses(conn, *id)?;
+    }
+
+    Ok(entries)
+}
+
+//
+// Private functions
+//
+
+// Matches a normalized headword column in full, or by prefix
+fn headword_condition(column: &str, normalized: String, prefix: bool) -> (String, Vec<Value>) {
+    if !prefix {
+        return (format!("{} = ?1", column), vec![Value::Text(normalized)]);
+    }
+
+    let escaped = normalized
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    (
+        format!("{} LIKE ?1 ESCAPE '\\'", column),
+        vec![Value::Text(format!("{}%", escaped))],
+    )
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    fn ids(conn: &Connection, search: &Search) -> Vec<i64> {
+        super::search(conn, search)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    fn setup() -> Connection {
+        test_db(&[
+            (
+                3,
+                "<div><hw><pa>آب</pa> <i>āb</i></hw>, Water; lustre</div>",
+            ),
+            (
+                3,
+                "<div><lang>A</lang> <hw><pa>آباد</pa> <i>ābād</i></hw>, Inhabited</div>",
+            ),
+            (
+                470,
+                "<div><hw><pa>خانه</pa> <i>ḵẖāna</i></hw>, A house; a water-closet; \
+                خانه داری <i>ḵẖāna-dārī</i>, Housekeeping</div>",
+            ),
+        ])
+    }
+
+    #[test]
+    fn headwords() {
+        let conn = setup();
+
+        let persian = |text: &str, prefix| {
+            Search::new(Query::Headword {
+                script: Script::Persian,
+                text: text.to_owned(),
+                prefix,
+            })
+        };
+        let latin = |text: &str, prefix| {
+            Search::new(Query::Headword {
+                script: Script::Latin,
+                text: text.to_owned(),
+                prefix,
+            })
+        };
+
+        assert_eq!(ids(&conn, &persian("اب", false)), vec![1]);
+        assert_eq!(ids(&conn, &persian("آب", true)), vec![1, 2]);
+        assert_eq!(ids(&conn, &latin("khana", false)), vec![3]);
+        assert_eq!(ids(&conn, &latin("ab", true)), vec![1, 2]);
+        assert!(ids(&conn, &latin("%", true)).is_empty());
+
+        let results = search(&conn, &latin("āb", false)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].1.headword_persian, "آب");
+        assert_eq!(results[0].1.senses.len(), 2);
+    }
+
+    #[test]
+    fn phrases() {
+        let conn = setup();
+
+        let phrase = |text: &str, prefix| {
+            Search::new(Query::Phrase {
+                text: text.to_owned(),
+                prefix,
+            })
+        };
+
+        assert_eq!(ids(&conn, &phrase("خانه داری", false)), vec![3]);
+        assert_eq!(ids(&conn, &phrase("خانه\u{200C}داري", false)), vec![3]);
+        assert_eq!(ids(&conn, &phrase("خانهٔ", true)), vec![3]);
+        assert!(ids(&conn, &phrase("خانه", false)).is_empty());
+        assert!(ids(&conn, &phrase("\u{064E}", true)).is_empty());
+    }
+
+    #[test]
+    fn filters() {
+        let conn = setup();
+
+        assert_eq!(
+            ids(&conn, &Search::new(Query::Definitions("water".to_owned()))),
+            vec![1, 3]
+        );
+        assert_eq!(ids(&conn, &Search::new(Query::Page(3))), vec![1, 2]);
+        assert_eq!(ids(&conn, &Search::new(Query::Abjad(3))), vec![1]);
+
+        let arabic = Search {
+            lang: Some(Lang::Arabic),
+            ..Search::new(Query::Page(3))
+        };
+        assert_eq!(ids(&conn, &arabic), vec![2]);
+
+        let limited = Search {
+            limit: Some(1),
+            ..Search::new(Query::Page(3))
+        };
+        assert_eq!(ids(&conn, &limited), vec![1]);
+    }
+}
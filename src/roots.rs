@@ -0,0 +1,191 @@
+use crate::langs::Lang;
+use crate::normalize::normalize_for_search;
+
+//
+// Constants
+//
+
+// Derivational affixes, longest first; what is left must still have three letters
+const PREFIXES: [&str; 8] = ["است", "مست", "مت", "من", "ال", "م", "ت", "ا"];
+const SUFFIXES: [&str; 7] = ["یات", "ات", "ین", "ون", "ان", "ه", "ی"];
+
+// Long vowels (and the letters that carry them) are left out of a root except at its start
+const WEAK: [char; 3] = ['\u{0627}', '\u{0648}', '\u{06CC}'];
+
+//
+// Public functions
+//
+
+// Possible triliteral (or quadriliteral) roots of a headword, most likely first, as normalized
+// letters joined by hyphens, e.g., "ک-ت-ب"; hollow and defective roots are not restored
+#[must_use]
+pub fn candidate_roots(headword_persian: &str) -> Vec<String> {
+    let normalized = normalize_for_search(headword_persian);
+    let Some(word) = normalized.split(' ').next().filter(|w| !w.is_empty()) else {
+        return Vec::new();
+    };
+
+    let mut stems = vec![word.to_owned()];
+
+    // Suffixes first, then up to two prefixes, e.g., "ال" and "م"
+    for strip in [strip_suffix, strip_prefix, strip_prefix] {
+        let mut next = Vec::new();
+
+        for stem in &stems {
+            next.extend(strip(stem));
+        }
+
+        stems.extend(next);
+    }
+
+    let mut triliteral = Vec::new();
+    let mut quadriliteral = Vec::new();
+
+    for stem in &stems {
+        let mut letters: Vec<char> = Vec::new();
+
+        for (i, c) in stem.chars().enumerate() {
+            if i == 0 || !WEAK.contains(&c) {
+                letters.push(c);
+            }
+        }
+
+        // Geminate roots lose their doubled letter along with the shadda
+        if letters.len() == 2 {
+            letters.push(letters[1]);
+        }
+
+        let root = letters
+            .iter()
+            .map(char::to_string)
+            .collect::<Vec<_>>()
+            .join("-");
+
+        let bucket = match letters.len() {
+            3 => &mut triliteral,
+            4 => &mut quadriliteral,
+            _ => continue,
+        };
+
+        if !bucket.contains(&root) {
+            bucket.push(root);
+        }
+    }
+
+    triliteral.extend(quadriliteral);
+    triliteral
+}
+
+// The root stored for an entry: only words marked as Arabic have one
+#[must_use]
+pub fn entry_root(lang: Lang, headword_persian: &str) -> Option<String> {
+    if !lang.includes_arabic() {
+        return None;
+    }
+
+    candidate_roots(headword_persian).into_iter().next()
+}
+
+// Accepts a root with or without separators, e.g., "کتب", "ك-ت-ب" or "ک ت ب"
+#[must_use]
+pub fn normalize_root(input: &str) -> String {
+    normalize_for_search(input)
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-'))
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+//
+// Private functions
+//
+
+fn strip_suffix(stem: &str) -> Vec<String> {
+    SUFFIXES
+        .iter()
+        .filter_map(|suffix| stem.strip_suffix(suffix))
+        .filter(|rest| rest.chars().count() >= 3)
+        .map(str::to_owned)
+        .collect()
+}
+
+fn strip_prefix(stem: &str) -> Vec<String> {
+    PREFIXES
+        .iter()
+        .filter_map(|prefix| stem.strip_prefix(prefix))
+        .filter(|rest| rest.chars().count() >= 3)
+        .map(str::to_owned)
+        .collect()
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{Query, Search, search};
+    use crate::test_db;
+
+    fn first_root(headword: &str) -> Option<String> {
+        candidate_roots(headword).into_iter().next()
+    }
+
+    #[test]
+    fn derivation() {
+        for headword in ["کتاب", "كاتب", "مکتوب", "مکتب", "مکاتیب", "کتب"]
+        {
+            assert_eq!(
+                first_root(headword).as_deref(),
+                Some("ک-ت-ب"),
+                "{}",
+                headword
+            );
+        }
+
+        assert_eq!(first_root("تعلیم").as_deref(), Some("ع-ل-م"));
+        assert_eq!(first_root("معلم").as_deref(), Some("ع-ل-م"));
+        assert_eq!(first_root("استقبال").as_deref(), Some("ق-ب-ل"));
+        assert_eq!(first_root("مدرسه").as_deref(), Some("د-ر-س"));
+        assert_eq!(first_root("اسلام").as_deref(), Some("س-ل-م"));
+        assert_eq!(first_root("أمر").as_deref(), Some("ا-م-ر"));
+        assert_eq!(first_root("شدّ").as_deref(), Some("ش-د-د"));
+        assert!(candidate_roots("ترجمه").contains(&"ت-ر-ج-م".to_owned()));
+
+        assert_eq!(normalize_root("كتب"), "ک-ت-ب");
+        assert_eq!(normalize_root("ک - ت - ب"), "ک-ت-ب");
+    }
+
+    #[test]
+    fn lookup() {
+        let conn = test_db(&[
+            (
+                1020,
+                "<div><lang>A</lang> <hw><pa>کتاب</pa> <i>kitāb</i></hw>, A book</div>",
+            ),
+            (
+                1020,
+                "<div><lang>a</lang> <hw><pa>مکتوب</pa> <i>maktūb</i></hw>, Written; a letter</div>",
+            ),
+            (
+                1020,
+                "<div><hw><pa>کتان</pa> <i>katān</i></hw>, Linen</div>",
+            ),
+        ]);
+
+        let results = search(&conn, &Search::new(Query::Root("ك ت ب".to_owned()))).unwrap();
+        let ids: Vec<i64> = results.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(results[0].1.root.as_deref(), Some("ک-ت-ب"));
+
+        // Not marked as Arabic, so not given a root
+        let root: Option<String> = conn
+            .query_row("SELECT root FROM entries WHERE id = 3", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(root, None);
+    }
+}
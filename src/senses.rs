@@ -0,0 +1,377 @@
+use regex::Regex;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::normalize::normalize_for_search;
+use crate::phrases::{Phrase, extract_phrases};
+use crate::xrefs::{CrossRef, extract_cross_refs};
+
+//
+// Type definitions
+//
+
+// One meaning within the definitions of an entry, in its Markdown form
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Default, Serialize, Deserialize)]
+pub struct Sense {
+    pub number: Option<String>, // "1", "2", or "a", "b" for sub-senses; None if unnumbered
+    pub parent: Option<usize>,  // Index of the enclosing sense, for sub-senses
+    pub labels: Vec<String>,    // Grammatical labels that introduce the sense, e.g., "s.", "pl."
+    pub text: String,
+    pub phrases: Vec<Phrase>,
+    #[serde(default)]
+    pub cross_refs: Vec<CrossRef>, // Not stored, but found again in the text on loading
+}
+
+//
+// Constants
+//
+
+// Longest first, so that e.g. "n. of act." is not read as "n."
+pub const GRAMMAR_LABELS: [&str; 24] = [
+    "n. of act.",
+    "n. of place",
+    "n. of unity",
+    "v.n.",
+    "n.u.",
+    "interj.",
+    "pron.",
+    "prep.",
+    "conj.",
+    "part.",
+    "comp.",
+    "fem.",
+    "dim.",
+    "adv.",
+    "inf.",
+    "imp.",
+    "pl.",
+    "du.",
+    "pr.",
+    "s.",
+    "a.",
+    "n.",
+    "m.",
+    "f.",
+];
+
+//
+// Public functions
+//
+
+// Splits Markdown definitions (as produced by `except_headword`) into senses
+#[must_use]
+pub fn parse_senses(definitions: &str) -> Vec<Sense> {
+    let re_marker = Regex::new(
+        r"(?:^|\s)(?:\*\*)?\((\d{1,2}|[a-h])\)(?:\*\*)?|(?:^|[;:]\s*)(?:\*\*)?(\d{1,2})\.(?:\*\*)?\s",
+    )
+    .unwrap();
+
+    let mut senses = Vec::new();
+    let mut start = 0;
+    let mut pending: Option<String> = None;
+
+    for caps in re_marker.captures_iter(definitions) {
+        let whole = caps.get(0).unwrap();
+        push_text(
+            &mut senses,
+            pending.take().as_deref(),
+            &definitions[start..whole.start()],
+        );
+
+        pending = caps
+            .get(1)
+            .or_else(|| caps.get(2))
+            .map(|m| m.as_str().to_owned());
+        start = whole.end();
+    }
+
+    push_text(&mut senses, pending.as_deref(), &definitions[start..]);
+
+    // Sub-senses ("a", "b", ...) belong to the most recent numbered sense
+    let mut last_top = None;
+
+    for (i, sense) in senses.iter_mut().enumerate() {
+        match sense.number.as_deref() {
+            Some(n) if n.starts_with(|c: char| c.is_ascii_digit()) => last_top = Some(i),
+            Some(_) => sense.parent = last_top,
+            None => {}
+        }
+    }
+
+    senses
+}
+
+// Replaces any senses (and phrases) already recorded for the entry
+pub fn set_senses(conn: &Connection, entry_id: i64, senses: &[Sense]) -> Result<(), Error> {
+    conn.execute("DELETE FROM senses WHERE entry_id = ?", [entry_id])?;
+    conn.execute("DELETE FROM phrases WHERE entry_id = ?", [entry_id])?;
+
+    let mut stmt_sense = conn.prepare_cached(
+        "INSERT INTO senses (entry_id, position, parent_position, number, labels, text)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    let mut stmt_phrase = conn.prepare_cached(
+        "INSERT INTO phrases (
+            entry_id, sense_position, position, persian, persian_search, latin, gloss
+        )
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+
+    for (i, sense) in senses.iter().enumerate() {
+        stmt_sense.execute((
+            entry_id,
+            i,
+            sense.parent,
+            &sense.number,
+            sense.labels.join(" "),
+            &sense.text,
+        ))?;
+
+        for (j, phrase) in sense.phrases.iter().enumerate() {
+            stmt_phrase.execute((
+                entry_id,
+                i,
+                j,
+                &phrase.persian,
+                normalize_for_search(&phrase.persian),
+                &phrase.latin,
+                &phrase.gloss,
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn load_senses(conn: &Connection, entry_id: i64) -> Result<Vec<Sense>, Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT parent_position, number, labels, text FROM senses
+        WHERE entry_id = ?
+        ORDER BY position",
+    )?;
+
+    let mut senses = stmt
+        .query_map([entry_id], |row| {
+            let labels: String = row.get(2)?;
+            let text: String = row.get(3)?;
+
+            Ok(Sense {
+                parent: row.get(0)?,
+                number: row.get(1)?,
+                labels: labels.split_whitespace().map(ToOwned::to_owned).collect(),
+                phrases: Vec::new(),
+                cross_refs: extract_cross_refs(&text),
+                text,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT sense_position, persian, latin, gloss FROM phrases
+        WHERE entry_id = ?
+        ORDER BY sense_position, position",
+    )?;
+    let mut rows = stmt.query([entry_id])?;
+
+    while let Some(row) = rows.next()? {
+        let sense_position: usize = row.get(0)?;
+
+        if let Some(sense) = senses.get_mut(sense_position) {
+            sense.phrases.push(Phrase {
+                persian: row.get(1)?,
+                latin: row.get(2)?,
+                gloss: row.get(3)?,
+            });
+        }
+    }
+
+    Ok(senses)
+}
+
+//
+// Private functions
+//
+
+fn push_text(senses: &mut Vec<Sense>, number: Option<&str>, text: &str) {
+    // Unnumbered stretches are split into their semicolon-separated meanings
+    let parts = if number.is_some() {
+        vec![text]
+    } else {
+        split_top_level(text)
+    };
+
+    for part in parts {
+        let (labels, rest) = take_labels(part);
+        let text = rest
+            .trim()
+            .trim_end_matches([';', ','])
+            .trim_end()
+            .to_owned();
+
+        if text.is_empty() && labels.is_empty() && number.is_none() {
+            continue;
+        }
+
+        senses.push(Sense {
+            number: number.map(ToOwned::to_owned),
+            parent: None,
+            labels,
+            phrases: extract_phrases(&text),
+            cross_refs: extract_cross_refs(&text),
+            text,
+        });
+    }
+}
+
+// Splits on semicolons outside of parentheses and brackets
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0_i32;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ';' if depth <= 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&text[start..]);
+    parts
+}
+
+fn take_labels(text: &str) -> (Vec<String>, &str) {
+    let mut labels = Vec::new();
+    let mut rest = text.trim_start_matches([' ', ',']);
+
+    'outer: loop {
+        let unstarred = rest.trim_start_matches('*');
+        let stars = rest.len() - unstarred.len();
+
+        for label in GRAMMAR_LABELS {
+            let Some(after) = unstarred.strip_prefix(label) else {
+                continue;
+            };
+
+            let after = after.strip_prefix(&"*".repeat(stars)).unwrap_or(after);
+
+            // A label must stand alone, e.g., not the "a." of "a.m."
+            if after.is_empty() || after.starts_with([' ', ',', ';']) {
+                labels.push(label.to_owned());
+                rest = after.trim_start_matches([' ', ',']);
+                continue 'outer;
+            }
+        }
+
+        break;
+    }
+
+    (labels, rest)
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xrefs::RefKind;
+    use crate::{ensure_table, insert_row, parse_entry};
+
+    #[test]
+    fn unnumbered() {
+        let senses =
+            parse_senses("*s.* Water; lustre (of a gem; of a sword); آب رو *āb-rū*, Honour");
+        assert_eq!(senses.len(), 3);
+
+        assert_eq!(senses[0].labels, vec!["s."]);
+        assert_eq!(senses[0].text, "Water");
+        assert_eq!(senses[1].text, "lustre (of a gem; of a sword)");
+        assert_eq!(
+            senses[2].phrases,
+            vec![Phrase {
+                persian: "آب رو".to_owned(),
+                latin: Some("āb-rū".to_owned()),
+                gloss: Some("Honour".to_owned()),
+            }]
+        );
+    }
+
+    #[test]
+    fn numbered() {
+        let senses = parse_senses("a. (1) Great; large. (2) pl. Elders: (a) chiefs; (b) ancestors");
+        let numbers: Vec<_> = senses.iter().map(|s| s.number.as_deref()).collect();
+        assert_eq!(
+            numbers,
+            vec![None, Some("1"), Some("2"), Some("a"), Some("b")]
+        );
+
+        assert_eq!(senses[0].labels, vec!["a."]);
+        assert_eq!(senses[0].text, "");
+        assert_eq!(senses[1].text, "Great; large.");
+        assert_eq!(senses[2].labels, vec!["pl."]);
+        assert_eq!(senses[3].parent, Some(2));
+        assert_eq!(senses[4].text, "ancestors");
+    }
+
+    #[test]
+    fn cross_refs() {
+        let senses = parse_senses("Water; see آب رو *āb-rū*; (1) Honour, cf. ماء");
+        assert!(senses[0].cross_refs.is_empty());
+
+        assert_eq!(senses[1].cross_refs.len(), 1);
+        assert_eq!(senses[1].cross_refs[0].kind, RefKind::See);
+        assert_eq!(
+            senses[1].cross_refs[0].target_latin.as_deref(),
+            Some("āb-rū")
+        );
+
+        assert_eq!(senses[2].number.as_deref(), Some("1"));
+        assert_eq!(senses[2].cross_refs.len(), 1);
+        assert_eq!(senses[2].cross_refs[0].kind, RefKind::Compare);
+        assert_eq!(
+            senses[2].cross_refs[0].target_persian.as_deref(),
+            Some("ماء")
+        );
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(
+            take_labels("s. pl. Waters"),
+            (vec!["s.".to_owned(), "pl.".to_owned()], "Waters")
+        );
+        assert_eq!(
+            take_labels("*n. of act.* Going"),
+            (vec!["n. of act.".to_owned()], "Going")
+        );
+        assert_eq!(take_labels("a.m. Morning"), (vec![], "a.m. Morning"));
+    }
+
+    #[test]
+    fn storage() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_table(&conn).unwrap();
+
+        let entry = parse_entry(
+            3,
+            1,
+            "<div><hw><pa>آب</pa> <i>āb</i></hw>, s. Water; آب رو <i>āb-rū</i>, Honour, \
+            cf. ماء</div>"
+                .to_owned(),
+        )
+        .unwrap();
+        assert_eq!(entry.senses.len(), 2);
+        assert_eq!(entry.senses[1].cross_refs.len(), 1);
+
+        let id = insert_row(&conn, entry.clone()).unwrap();
+        assert_eq!(load_senses(&conn, id).unwrap(), entry.senses);
+    }
+}
@@ -0,0 +1,213 @@
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::articles::{
+    ArticleFormat, DICTIONARY_AUTHOR, DICTIONARY_DESCRIPTION, DICTIONARY_TITLE, DICTIONARY_URL,
+    dictionary_path, index_words, render_article,
+};
+use crate::dictzip::write_dictzip;
+use crate::error::{Error, Location};
+use crate::for_each_entry;
+
+//
+// Type definitions
+//
+
+// The three files of a StarDict dictionary, before the article data is compressed
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+pub struct StarDict {
+    pub ifo: String,
+    pub idx: Vec<u8>,
+    pub dict: Vec<u8>,
+    pub entries: usize,
+}
+
+//
+// Public functions
+//
+
+// One article per entry, found under both its Persian and its Latin headword
+pub fn build_stardict(conn: &Connection, format: ArticleFormat) -> Result<StarDict, Error> {
+    let mut dict = Vec::new();
+    let mut words: Vec<(String, u32, u32)> = Vec::new();
+    let mut entries = 0;
+
+    for_each_entry(conn, |_, entry| {
+        let article = render_article(&entry, format);
+        let location = Location::entry(entry.page, entry.position);
+        let offset = to_u32(dict.len(), location)?;
+        let size = to_u32(article.len(), location)?;
+
+        dict.extend_from_slice(article.as_bytes());
+
+        for word in index_words(&entry) {
+            words.push((word, offset, size));
+        }
+
+        entries += 1;
+        Ok(())
+    })?;
+
+    // Readers search the index by bisection, in this order
+    words.sort_by(|a, b| stardict_cmp(&a.0, &b.0).then(a.1.cmp(&b.1)));
+
+    let mut idx = Vec::new();
+
+    for (word, offset, size) in &words {
+        idx.extend_from_slice(word.as_bytes());
+        idx.push(0);
+        idx.extend_from_slice(&offset.to_be_bytes());
+        idx.extend_from_slice(&size.to_be_bytes());
+    }
+
+    let ifo = format!(
+        "StarDict's dict ifo file\nversion=2.4.2\nbookname={}\nwordcount={}\nidxfilesize={}\n\
+         author={}\nwebsite={}\ndescription={}\nsametypesequence={}\n",
+        DICTIONARY_TITLE,
+        words.len(),
+        idx.len(),
+        DICTIONARY_AUTHOR,
+        DICTIONARY_URL,
+        DICTIONARY_DESCRIPTION,
+        match format {
+            ArticleFormat::Html => "h",
+            ArticleFormat::Text => "m",
+        }
+    );
+
+    Ok(StarDict {
+        ifo,
+        idx,
+        dict,
+        entries,
+    })
+}
+
+// Writes `base` plus ".ifo", ".idx" and ".dict.dz"
+pub fn write_stardict(
+    conn: &Connection,
+    base: &Path,
+    format: ArticleFormat,
+) -> Result<usize, Error> {
+    let stardict = build_stardict(conn, format)?;
+
+    fs::write(dictionary_path(base, ".ifo"), &stardict.ifo)?;
+    fs::write(dictionary_path(base, ".idx"), &stardict.idx)?;
+
+    let mut out = BufWriter::new(File::create(dictionary_path(base, ".dict.dz"))?);
+    write_dictzip(&stardict.dict, &mut out)?;
+    out.flush()?;
+
+    Ok(stardict.entries)
+}
+
+//
+// Private functions
+//
+
+// As `stardict_strcmp`: ASCII letters compared without case, then bytes as a tie-breaker
+fn stardict_cmp(a: &str, b: &str) -> Ordering {
+    let folded = |s: &str| {
+        s.bytes()
+            .map(|b| b.to_ascii_lowercase())
+            .collect::<Vec<_>>()
+    };
+    folded(a).cmp(&folded(b)).then_with(|| a.cmp(b))
+}
+
+fn to_u32(value: usize, location: Location) -> Result<u32, Error> {
+    u32::try_from(value).map_err(|_| Error::Parse {
+        location,
+        message: "StarDict article data exceeds 4 GiB".to_owned(),
+    })
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    fn setup() -> Connection {
+        test_db(&[
+            (
+                3,
+                "<div><lang>a</lang> <hw><pa>آب</pa> <i>āb</i></hw>, s. Water; <i>lustre</i></div>",
+            ),
+            (3, "<div><hw><pa>باد</pa> <i>bād</i></hw>, Wind</div>"),
+            (
+                3,
+                "<div><hw><pa>Ab</pa> <i>Ab</i></hw>, A test of case</div>",
+            ),
+        ])
+    }
+
+    // Reads the index back as (word, article)
+    fn articles(stardict: &StarDict) -> Vec<(String, String)> {
+        let mut found = Vec::new();
+        let mut rest = stardict.idx.as_slice();
+
+        while !rest.is_empty() {
+            let end = rest.iter().position(|b| *b == 0).unwrap();
+            let word = String::from_utf8(rest[..end].to_vec()).unwrap();
+            let number = |i: usize| {
+                u32::from_be_bytes(rest[end + 1 + i..end + 5 + i].try_into().unwrap()) as usize
+            };
+            let (offset, size) = (number(0), number(4));
+            let article = String::from_utf8(stardict.dict[offset..offset + size].to_vec());
+
+            found.push((word, article.unwrap()));
+            rest = &rest[end + 9..];
+        }
+
+        found
+    }
+
+    #[test]
+    fn index() {
+        let stardict = build_stardict(&setup(), ArticleFormat::Html).unwrap();
+        assert_eq!(stardict.entries, 3);
+
+        let found = articles(&stardict);
+        let words: Vec<&str> = found.iter().map(|(w, _)| w.as_str()).collect();
+        assert_eq!(words, vec!["Ab", "bād", "āb", "آب", "باد"]);
+
+        assert_eq!(
+            found[3].1,
+            "<b>آب <i>āb</i></b> <small>[Arabic &amp; Persian]</small><br/>s. Water; <i>lustre</i>"
+        );
+        assert_eq!(found[2].1, found[3].1);
+
+        assert!(
+            stardict
+                .ifo
+                .starts_with("StarDict's dict ifo file\nversion=2.4.2\n")
+        );
+        assert!(stardict.ifo.contains("\nwordcount=5\n"));
+        assert!(
+            stardict
+                .ifo
+                .contains(&format!("\nidxfilesize={}\n", stardict.idx.len()))
+        );
+        assert!(stardict.ifo.ends_with("\nsametypesequence=h\n"));
+    }
+
+    #[test]
+    fn plain_text() {
+        let stardict = build_stardict(&setup(), ArticleFormat::Text).unwrap();
+        let found = articles(&stardict);
+
+        assert_eq!(found[1].1, "باد bād\nWind");
+        assert!(stardict.ifo.ends_with("\nsametypesequence=m\n"));
+
+        assert_eq!(stardict_cmp("ab", "AC"), Ordering::Less);
+        assert_eq!(stardict_cmp("AB", "ab"), Ordering::Less);
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt::Write as _;
+use std::io::Write;
+
+use rusqlite::Connection;
+
+use crate::error::Error;
+use crate::langs::Lang;
+use crate::senses::Sense;
+use crate::xml::{TEI_TAGS, escape_xml, markdown_to_text, markdown_to_xml, write_senses};
+use crate::{Entry, for_each_entry};
+
+//
+// Constants
+//
+
+const TEI_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TEI xmlns="http://www.tei-c.org/ns/1.0" xml:lang="en">
+  <teiHeader>
+    <fileDesc>
+      <titleStmt>
+        <title>A Comprehensive Persian-English Dictionary</title>
+        <author>Francis Joseph Steingass</author>
+      </titleStmt>
+      <publicationStmt>
+        <p>Converted from the Digital South Asia Library edition</p>
+        <ptr target="https://dsal.uchicago.edu/dictionaries/steingass/"/>
+      </publicationStmt>
+      <sourceDesc>
+        <bibl>Steingass, Francis Joseph. A Comprehensive Persian-English Dictionary. London: Routledge &amp; K. Paul, 1892.</bibl>
+      </sourceDesc>
+    </fileDesc>
+  </teiHeader>
+  <text>
+    <body>
+"#;
+
+const TEI_FOOTER: &str = "    </body>\n  </text>\n</TEI>\n";
+
+//
+// Public functions
+//
+
+// A TEI Lex-0 document with one `<entry>` per row, in ID order, and a `<pb>` wherever the
+// page changes
+pub fn write_tei<W: Write>(conn: &Connection, out: &mut W) -> Result<usize, Error> {
+    write!(out, "{}", TEI_HEADER)?;
+
+    let mut count = 0;
+    let mut page = None;
+
+    for_each_entry(conn, |_, entry| {
+        if page != Some(entry.page) {
+            writeln!(out, "      <pb n=\"{}\"/>", entry.page)?;
+            page = Some(entry.page);
+        }
+
+        write!(out, "{}", tei_entry(&entry))?;
+
+        count += 1;
+        Ok(())
+    })?;
+
+    write!(out, "{}", TEI_FOOTER)?;
+
+    Ok(count)
+}
+
+//
+// Private functions
+//
+
+fn tei_entry(entry: &Entry) -> String {
+    let id = format!("steingass-{}", entry.uid);
+    let mut xml = format!(
+        "      <entry xml:id=\"{}\" xml:lang=\"fa\">\n        <form type=\"lemma\">\n",
+        escape_xml(&id)
+    );
+
+    writeln!(
+        xml,
+        "          <orth xml:lang=\"fa-Arab\">{}</orth>",
+        escape_xml(&entry.headword_persian)
+    )
+    .unwrap();
+
+    let latin = markdown_to_text(&entry.headword_latin);
+
+    if !latin.is_empty() {
+        writeln!(
+            xml,
+            "          <pron notation=\"steingass\" xml:lang=\"fa-Latn\">{}</pron>",
+            escape_xml(&latin)
+        )
+        .unwrap();
+    }
+
+    xml.push_str("        </form>\n");
+
+    if entry.lang != Lang::Unmarked {
+        xml.push_str("        <etym>\n");
+
+        for lang in entry.lang.as_str().split(" & ") {
+            writeln!(xml, "          <lang>{}</lang>", escape_xml(lang)).unwrap();
+        }
+
+        xml.push_str("        </etym>\n");
+    }
+
+    if entry.senses.is_empty() {
+        if !entry.definitions.is_empty() {
+            writeln!(
+                xml,
+                "        <sense xml:id=\"{}-1\">\n          <def>{}</def>\n        </sense>",
+                escape_xml(&id),
+                markdown_to_xml(&entry.definitions, &TEI_TAGS)
+            )
+            .unwrap();
+        }
+    } else {
+        write_senses(
+            &mut xml,
+            &entry.senses,
+            4,
+            |xml, indent, i| tei_sense(xml, indent, &id, i, &entry.senses[i]),
+            |xml, indent| writeln!(xml, "{}</sense>", indent).unwrap(),
+        );
+    }
+
+    xml.push_str("      </entry>\n");
+    xml
+}
+
+// Opens a `<sense>` with its number, grammatical labels and definition
+fn tei_sense(xml: &mut String, indent: &str, entry_id: &str, index: usize, sense: &Sense) {
+    write!(
+        xml,
+        "{}<sense xml:id=\"{}-{}\"",
+        indent,
+        escape_xml(entry_id),
+        index + 1
+    )
+    .unwrap();
+
+    if let Some(number) = &sense.number {
+        write!(xml, " n=\"{}\"", escape_xml(number)).unwrap();
+    }
+
+    xml.push_str(">\n");
+
+    if !sense.labels.is_empty() {
+        writeln!(xml, "{}  <gramGrp>", indent).unwrap();
+
+        for label in &sense.labels {
+            writeln!(xml, "{}    <gram>{}</gram>", indent, escape_xml(label)).unwrap();
+        }
+
+        writeln!(xml, "{}  </gramGrp>", indent).unwrap();
+    }
+
+    if !sense.text.is_empty() {
+        writeln!(
+            xml,
+            "{}  <def>{}</def>",
+            indent,
+            markdown_to_xml(&sense.text, &TEI_TAGS)
+        )
+        .unwrap();
+    }
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    fn export(entries: &[(u16, &str)]) -> String {
+        let conn = test_db(entries);
+
+        let mut out = Vec::new();
+        assert_eq!(write_tei(&conn, &mut out).unwrap(), entries.len());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn well_formed() {
+        let xml = export(&[
+            (
+                3,
+                "<div><lang>a</lang> <hw><pa>آب</pa> <i>āb</i></hw>, s. Water; <b>(1)</b> Lustre, \
+                 <i>splendour</i>; <b>(a)</b> honour &amp; x<sup>2</sup> &lt;sic&gt;</div>",
+            ),
+            (3, "<div><hw><pa>آبا</pa> <i>ābā</i></hw>, Fathers</div>"),
+            (4, "<div><hw><pa>آباد</pa> <i>ābād</i></hw></div>"),
+        ]);
+
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let root = doc.root_element();
+        assert_eq!(root.tag_name().name(), "TEI");
+        assert_eq!(
+            root.tag_name().namespace(),
+            Some("http://www.tei-c.org/ns/1.0")
+        );
+
+        let pages: Vec<&str> = doc
+            .descendants()
+            .filter(|n| n.has_tag_name("pb"))
+            .filter_map(|n| n.attribute("n"))
+            .collect();
+        assert_eq!(pages, vec!["3", "4"]);
+
+        let entries: Vec<_> = doc
+            .descendants()
+            .filter(|n| n.has_tag_name("entry"))
+            .collect();
+        assert_eq!(entries.len(), 3);
+
+        let first = entries[0];
+        let text = |name: &str| {
+            first
+                .descendants()
+                .find(|n| n.has_tag_name(name))
+                .and_then(|n| n.text())
+        };
+        assert_eq!(text("orth"), Some("آب"));
+        assert_eq!(text("pron"), Some("āb"));
+        assert_eq!(text("gram"), Some("s."));
+
+        let langs: Vec<_> = first
+            .descendants()
+            .filter(|n| n.has_tag_name("lang"))
+            .filter_map(|n| n.text())
+            .collect();
+        assert_eq!(langs, vec!["Arabic", "Persian"]);
+
+        // The sub-sense sits inside its numbered sense
+        let sub = first
+            .descendants()
+            .find(|n| n.has_tag_name("sense") && n.attribute("n") == Some("a"))
+            .unwrap();
+        assert_eq!(sub.parent_element().unwrap().attribute("n"), Some("1"));
+
+        // Unmarked words have no etymology, and one without definitions no sense
+        assert!(!entries[1].descendants().any(|n| n.has_tag_name("etym")));
+        assert!(!entries[2].descendants().any(|n| n.has_tag_name("sense")));
+    }
+
+    #[test]
+    fn empty() {
+        let xml = export(&[]);
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        assert!(!doc.descendants().any(|n| n.has_tag_name("entry")));
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt::Write as _;
+use std::io::Write;
+
+use rusqlite::Connection;
+
+use crate::articles::{DICTIONARY_DESCRIPTION, DICTIONARY_TITLE, index_words};
+use crate::error::Error;
+use crate::langs::Lang;
+use crate::senses::Sense;
+use crate::xml::{HTML_TAGS, escape_xml, markdown_to_xml, write_senses};
+use crate::{Entry, for_each_entry};
+
+//
+// Constants
+//
+
+const XDXF_FOOTER: &str = "  </lexicon>\n</xdxf>\n";
+
+//
+// Public functions
+//
+
+// An XDXF dictionary in the logical format, with one `<ar>` per entry, in ID order
+pub fn write_xdxf<W: Write>(conn: &Connection, out: &mut W) -> Result<usize, Error> {
+    write!(
+        out,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <xdxf lang_from=\"PER\" lang_to=\"ENG\" format=\"logical\" revision=\"034\">\n  \
+         <meta_info>\n    <title>{}</title>\n    <full_title>{}</full_title>\n    \
+         <description>{}</description>\n  </meta_info>\n  <lexicon>\n",
+        escape_xml(DICTIONARY_TITLE),
+        escape_xml(DICTIONARY_TITLE),
+        escape_xml(DICTIONARY_DESCRIPTION)
+    )?;
+
+    let mut count = 0;
+
+    for_each_entry(conn, |_, entry| {
+        write!(out, "{}", xdxf_article(&entry))?;
+
+        count += 1;
+        Ok(())
+    })?;
+
+    write!(out, "{}", XDXF_FOOTER)?;
+
+    Ok(count)
+}
+
+//
+// Private functions
+//
+
+fn xdxf_article(entry: &Entry) -> String {
+    let mut xml = String::from("    <ar>\n");
+
+    for word in index_words(entry) {
+        writeln!(xml, "      <k>{}</k>", escape_xml(&word)).unwrap();
+    }
+
+    xml.push_str("      <def>\n");
+
+    // Unmarked words are Persian, and need no etymology
+    if entry.lang != Lang::Unmarked {
+        writeln!(
+            xml,
+            "        <etm>{}</etm>",
+            escape_xml(entry.lang.as_str())
+        )
+        .unwrap();
+    }
+
+    if entry.senses.is_empty() {
+        if !entry.definitions.is_empty() {
+            writeln!(
+                xml,
+                "        <deftext>{}</deftext>",
+                markdown_to_xml(&entry.definitions, &HTML_TAGS)
+            )
+            .unwrap();
+        }
+    } else {
+        write_senses(
+            &mut xml,
+            &entry.senses,
+            4,
+            |xml, indent, i| xdxf_sense(xml, indent, &entry.senses[i]),
+            |xml, indent| writeln!(xml, "{}</def>", indent).unwrap(),
+        );
+    }
+
+    xml.push_str("      </def>\n    </ar>\n");
+    xml
+}
+
+// Opens a `<def>` with the sense's labels and text
+fn xdxf_sense(xml: &mut String, indent: &str, sense: &Sense) {
+    writeln!(xml, "{}<def>", indent).unwrap();
+
+    for label in &sense.labels {
+        writeln!(xml, "{}  <gr>{}</gr>", indent, escape_xml(label)).unwrap();
+    }
+
+    let number = sense
+        .number
+        .as_ref()
+        .map(|n| format!("<b>({})</b> ", escape_xml(n)))
+        .unwrap_or_default();
+
+    if !sense.text.is_empty() || !number.is_empty() {
+        writeln!(
+            xml,
+            "{}  <deftext>{}{}</deftext>",
+            indent,
+            number,
+            markdown_to_xml(&sense.text, &HTML_TAGS)
+        )
+        .unwrap();
+    }
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    #[test]
+    fn well_formed() {
+        let conn = test_db(&[
+            (
+                3,
+                "<div><lang>a</lang> <hw><pa>آب</pa> <i>āb</i></hw>, s. Water; <b>(1)</b> Lustre, \
+                 <i>splendour</i>; <b>(a)</b> honour &amp; x<sup>2</sup></div>",
+            ),
+            (3, "<div><hw><pa>آباد</pa> <i>ābād</i></hw></div>"),
+        ]);
+
+        let mut out = Vec::new();
+        assert_eq!(write_xdxf(&conn, &mut out).unwrap(), 2);
+        let xml = String::from_utf8(out).unwrap();
+
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        assert_eq!(doc.root_element().attribute("lang_from"), Some("PER"));
+
+        let articles: Vec<_> = doc.descendants().filter(|n| n.has_tag_name("ar")).collect();
+        assert_eq!(articles.len(), 2);
+
+        let keys: Vec<_> = articles[0]
+            .children()
+            .filter(|n| n.has_tag_name("k"))
+            .filter_map(|n| n.text())
+            .collect();
+        assert_eq!(keys, vec!["آب", "āb"]);
+
+        let etymology = articles[0].descendants().find(|n| n.has_tag_name("etm"));
+        assert_eq!(etymology.unwrap().text(), Some("Arabic & Persian"));
+
+        // The sub-sense is a definition within the definition of sense 1
+        let sub = articles[0]
+            .descendants()
+            .find(|n| n.has_tag_name("deftext") && n.children().any(|c| c.text() == Some("(a)")))
+            .unwrap();
+        let parent = sub.parent_element().unwrap().parent_element().unwrap();
+        assert!(parent.descendants().any(|n| n.text() == Some("(1)")));
+
+        assert!(!articles[1].descendants().any(|n| n.has_tag_name("deftext")));
+    }
+}
@@ -0,0 +1,293 @@
+use crate::senses::Sense;
+
+//
+// Type definitions
+//
+
+// The markup an XML format uses for the inline formatting in our Markdown
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct InlineTags {
+    pub emph: (&'static str, &'static str),
+    pub strong: (&'static str, &'static str),
+    pub superscript: (&'static str, &'static str),
+    pub line_break: &'static str,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+enum Style {
+    Emph,
+    Strong,
+    Superscript,
+}
+
+//
+// Constants
+//
+
+pub const TEI_TAGS: InlineTags = InlineTags {
+    emph: ("<hi rend=\"italic\">", "</hi>"),
+    strong: ("<hi rend=\"bold\">", "</hi>"),
+    superscript: ("<hi rend=\"superscript\">", "</hi>"),
+    line_break: "<lb/>",
+};
+
+pub const HTML_TAGS: InlineTags = InlineTags {
+    emph: ("<i>", "</i>"),
+    strong: ("<b>", "</b>"),
+    superscript: ("<sup>", "</sup>"),
+    line_break: "<br/>",
+};
+
+//
+// Implementations
+//
+
+impl InlineTags {
+    const fn tags(&self, style: Style) -> (&'static str, &'static str) {
+        match style {
+            Style::Emph => self.emph,
+            Style::Strong => self.strong,
+            Style::Superscript => self.superscript,
+        }
+    }
+}
+
+//
+// Public functions
+//
+
+#[must_use]
+pub fn escape_xml(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+// Converts Markdown as written by `markdown::html_to_markdown` to well-formed XML content;
+// images are reduced to their alt text, and any emphasis left open is closed at the end
+#[must_use]
+pub fn markdown_to_xml(input: &str, tags: &InlineTags) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut open: Vec<Style> = Vec::new();
+    let mut rest = input;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("  \n") {
+            out.push_str(tags.line_break);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("&lt;") {
+            out.push_str("&lt;");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("&gt;") {
+            out.push_str("&gt;");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("<sup>") {
+            toggle(&mut out, &mut open, Style::Superscript, tags);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("</sup>") {
+            toggle(&mut out, &mut open, Style::Superscript, tags);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("***") {
+            // Opens strong emphasis, or closes both, innermost first
+            let closing = open.contains(&Style::Emph) && open.contains(&Style::Strong);
+            let styles = if closing && open.last() == Some(&Style::Emph) {
+                [Style::Emph, Style::Strong]
+            } else {
+                [Style::Strong, Style::Emph]
+            };
+
+            for style in styles {
+                toggle(&mut out, &mut open, style, tags);
+            }
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("**") {
+            toggle(&mut out, &mut open, Style::Strong, tags);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('*') {
+            toggle(&mut out, &mut open, Style::Emph, tags);
+            rest = after;
+        } else if let Some((alt, after)) = image(rest) {
+            out.push_str(&escape_xml(&unescape_markdown(alt)));
+            rest = after;
+        } else if c == '\\' && rest.len() > 1 {
+            let escaped = rest[1..].chars().next().unwrap();
+            out.push_str(&escape_xml(&escaped.to_string()));
+            rest = &rest[1 + escaped.len_utf8()..];
+        } else {
+            out.push_str(&escape_xml(&c.to_string()));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    while let Some(style) = open.pop() {
+        out.push_str(tags.tags(style).1);
+    }
+
+    out
+}
+
+// Drops the Markdown escapes, for text that is to be written without markup
+#[must_use]
+pub fn markdown_to_text(input: &str) -> String {
+    let mut text = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek().is_some() => text.push(chars.next().unwrap()),
+            '*' => {}
+            _ => text.push(c),
+        }
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("<sup>", "")
+        .replace("</sup>", "")
+        .replace("  \n", " ")
+}
+
+// Walks the sense tree from the top-level senses down, with each sense's indentation (two spaces
+// per level below `depth`) and index: `open` writes a sense as far as its sub-senses, and `close`
+// what follows them
+pub fn write_senses<O, C>(
+    xml: &mut String,
+    senses: &[Sense],
+    depth: usize,
+    mut open: O,
+    mut close: C,
+) where
+    O: FnMut(&mut String, &str, usize),
+    C: FnMut(&mut String, &str),
+{
+    for (i, sense) in senses.iter().enumerate() {
+        if sense.parent.is_none() {
+            write_sense(xml, senses, i, depth, &mut open, &mut close);
+        }
+    }
+}
+
+//
+// Private functions
+//
+
+// Sub-senses are written inside the sense they belong to
+fn write_sense<O, C>(
+    xml: &mut String,
+    senses: &[Sense],
+    index: usize,
+    depth: usize,
+    open: &mut O,
+    close: &mut C,
+) where
+    O: FnMut(&mut String, &str, usize),
+    C: FnMut(&mut String, &str),
+{
+    let indent = "  ".repeat(depth);
+    open(xml, &indent, index);
+
+    for (i, sub) in senses.iter().enumerate() {
+        if sub.parent == Some(index) {
+            write_sense(xml, senses, i, depth + 1, open, close);
+        }
+    }
+
+    close(xml, &indent);
+}
+
+// Opens the style, or closes it (and reopens anything opened inside it)
+fn toggle(out: &mut String, open: &mut Vec<Style>, style: Style, tags: &InlineTags) {
+    let Some(position) = open.iter().rposition(|s| *s == style) else {
+        out.push_str(tags.tags(style).0);
+        open.push(style);
+        return;
+    };
+
+    let inner = open.split_off(position + 1);
+
+    for s in inner.iter().rev() {
+        out.push_str(tags.tags(*s).1);
+    }
+
+    out.push_str(tags.tags(style).1);
+    open.pop();
+
+    for s in inner {
+        out.push_str(tags.tags(s).0);
+        open.push(s);
+    }
+}
+
+// `![alt](src "title")`, returning the alt text and what follows
+fn image(input: &str) -> Option<(&str, &str)> {
+    let rest = input.strip_prefix("![")?;
+    let alt_end = rest.find("](")?;
+    let close = rest[alt_end..].find(')')? + alt_end;
+
+    Some((&rest[..alt_end], &rest[close + 1..]))
+}
+
+fn unescape_markdown(input: &str) -> String {
+    let mut text = String::with_capacity(input.len());
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\'
+            && let Some(next) = chars.next()
+        {
+            text.push(next);
+        } else {
+            text.push(c);
+        }
+    }
+
+    text
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline() {
+        assert_eq!(
+            markdown_to_xml("آب *āb*, **Water**; ***rū*** & x<sup>2</sup>", &HTML_TAGS),
+            "آب <i>āb</i>, <b>Water</b>; <b><i>rū</i></b> &amp; x<sup>2</sup>"
+        );
+        assert_eq!(
+            markdown_to_xml("a\\*b \\[c\\] &lt;d&gt;  \ne", &TEI_TAGS),
+            "a*b [c] &lt;d&gt;<lb/>e"
+        );
+        assert_eq!(
+            markdown_to_xml("see ![a \\* b](img/x.gif \"t\") *open", &HTML_TAGS),
+            "see a * b <i>open</i>"
+        );
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            markdown_to_text("*āb*, **Water** a\\*b &lt;c&gt;"),
+            "āb, Water a*b <c>"
+        );
+        assert_eq!(
+            escape_xml("<a href=\"x\">&'"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&apos;"
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::fmt;
+use std::str::FromStr;
+
+use regex::{Captures, Regex};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::clean_simple;
+use crate::error::{Error, Location};
+use crate::for_each_entry;
+
+//
+// Type definitions
+//
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefKind {
+    See,     // "see ...", "vide ..."
+    QuaVide, // "... q.v."
+    Compare, // "cf. ..."
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub enum RefStatus {
+    Resolved,
+    Ambiguous, // More than one entry matches
+    Unresolved,
+}
+
+// A pointer from the definitions of an entry to another headword
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Serialize, Deserialize)]
+pub struct CrossRef {
+    pub kind: RefKind,
+    pub text: String, // The pointer as it appears in the definitions
+    pub target_persian: Option<String>,
+    pub target_latin: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+pub struct ResolveCounts {
+    pub resolved: usize,
+    pub ambiguous: usize,
+    pub unresolved: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct DanglingRef {
+    pub entry_id: i64,
+    pub entry_uid: String,
+    pub page: u16,
+    pub headword_full: String,
+    pub text: String,
+    pub status: RefStatus,
+}
+
+//
+// Implementations
+//
+
+impl RefKind {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::See => "see",
+            Self::QuaVide => "q.v.",
+            Self::Compare => "cf.",
+        }
+    }
+}
+
+impl RefStatus {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Resolved => "resolved",
+            Self::Ambiguous => "ambiguous",
+            Self::Unresolved => "unresolved",
+        }
+    }
+}
+
+impl FromStr for RefStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "resolved" => Ok(Self::Resolved),
+            "ambiguous" => Ok(Self::Ambiguous),
+            "unresolved" => Ok(Self::Unresolved),
+            _ => Err(Error::Parse {
+                location: Location::default(),
+                message: format!("Unrecognized cross-reference status: {}", s),
+            }),
+        }
+    }
+}
+
+impl CrossRef {
+    fn same_target(&self, other: &Self) -> bool {
+        self.target_persian == other.target_persian && self.target_latin == other.target_latin
+    }
+}
+
+impl fmt::Display for RefStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+//
+// Constants
+//
+
+// Persian or Arabic script, optionally followed by a transliteration; or a transliteration alone
+const TARGET: &str = r"(?:(?P<per>\p{Arabic}(?:[\p{Arabic}\u{200C} ]*\p{Arabic})?)(?:\s*\*(?P<lat>[^*\s;,.][^*]*)\*)?|\*(?P<lat_only>[^*\s;,.][^*]*)\*)";
+
+//
+// Public functions
+//
+
+// Finds the cross-references in Markdown definitions, as produced by `except_headword`
+#[must_use]
+pub fn extract_cross_refs(definitions: &str) -> Vec<CrossRef> {
+    let patterns = [
+        (
+            RefKind::QuaVide,
+            format!(r"{}\s*,?\s*\*?q\.\s?v\.\*?", TARGET),
+        ),
+        (
+            RefKind::See,
+            format!(
+                r"\b(?:[Ss]ee|[Vv]ide)\s+(?:also\s+)?(?:under\s+)?{}",
+                TARGET
+            ),
+        ),
+        (RefKind::Compare, format!(r"\b[Cc]f\.\s+{}", TARGET)),
+    ];
+
+    let mut refs: Vec<CrossRef> = Vec::new();
+
+    for (kind, pattern) in patterns {
+        let re = Regex::new(&pattern).unwrap();
+
+        for caps in re.captures_iter(definitions) {
+            let found = from_captures(kind, &caps);
+
+            // "see X q.v." points at X only once
+            if !refs.iter().any(|r| r.same_target(&found)) {
+                refs.push(found);
+            }
+        }
+    }
+
+    refs
+}
+
+// Rebuilds the whole table, since a reference can only be resolved once its target is in the DB
+pub fn resolve_cross_refs(conn: &Connection) -> Result<ResolveCounts, Error> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM cross_refs", [])?;
+
+    let mut counts = ResolveCounts::default();
+
+    let mut stmt_insert = tx.prepare(
+        "INSERT INTO cross_refs (
+            entry_id, position, kind, text, target_persian, target_latin, target_id, status
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?;
+
+    for_each_entry(&tx, |id, entry| {
+        for (i, cross_ref) in extract_cross_refs(&entry.definitions).iter().enumerate() {
+            let candidates = find_targets(&tx, id, cross_ref)?;
+
+            let (target_id, status) = match candidates.as_slice() {
+                [target] => (Some(*target), RefStatus::Resolved),
+                [] => (None, RefStatus::Unresolved),
+                _ => (None, RefStatus::Ambiguous),
+            };
+
+            match status {
+                RefStatus::Resolved => counts.resolved += 1,
+                RefStatus::Ambiguous => counts.ambiguous += 1,
+                RefStatus::Unresolved => counts.unresolved += 1,
+            }
+
+            stmt_insert.execute((
+                id,
+                i,
+                cross_ref.kind.as_str(),
+                &cross_ref.text,
+                &cross_ref.target_persian,
+                &cross_ref.target_latin,
+                target_id,
+                status.as_str(),
+            ))?;
+        }
+
+        Ok(())
+    })?;
+
+    drop(stmt_insert);
+    tx.commit()?;
+
+    Ok(counts)
+}
+
+pub fn dangling_refs(conn: &Connection) -> Result<Vec<DanglingRef>, Error> {
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.uid, e.page, e.headword_full, x.text, x.status
+        FROM cross_refs x
+        JOIN entries e ON e.id = x.entry_id
+        WHERE x.status != 'resolved'
+        ORDER BY e.id, x.position",
+    )?;
+
+    let mut rows = stmt.query([])?;
+    let mut dangling = Vec::new();
+
+    while let Some(row) = rows.next()? {
+        let status: String = row.get(5)?;
+
+        dangling.push(DanglingRef {
+            entry_id: row.get(0)?,
+            entry_uid: row.get(1)?,
+            page: row.get(2)?,
+            headword_full: row.get(3)?,
+            text: row.get(4)?,
+            status: RefStatus::from_str(&status)?,
+        });
+    }
+
+    Ok(dangling)
+}
+
+//
+// Private functions
+//
+
+fn from_captures(kind: RefKind, caps: &Captures) -> CrossRef {
+    let target_persian = caps.name("per").map(|m| clean_simple(m.as_str()));
+    let target_latin = caps
+        .name("lat")
+        .or_else(|| caps.name("lat_only"))
+        .map(|m| clean_simple(m.as_str()));
+
+    CrossRef {
+        kind,
+        text: caps[0].trim().to_owned(),
+        target_persian,
+        target_latin,
+    }
+}
+
+// Entries (other than the one pointing) whose headword matches the target
+fn find_targets(conn: &Connection, from_id: i64, cross_ref: &CrossRef) -> Result<Vec<i64>, Error> {
+    // Latin headwords are stored in their Markdown (italic) form
+    let latin_starred = cross_ref.target_latin.as_ref().map(|l| format!("*{}*", l));
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT id FROM entries
+        WHERE id != ?1
+        AND (?2 IS NULL OR headword_persian = ?2)
+        AND (?3 IS NULL OR headword_latin = ?3 OR headword_latin = ?4)
+        ORDER BY id",
+    )?;
+
+    let ids = stmt
+        .query_map(
+            (
+                from_id,
+                &cross_ref.target_persian,
+                &cross_ref.target_latin,
+                &latin_starred,
+            ),
+            |row| row.get(0),
+        )?
+        .collect::<Result<Vec<i64>, _>>()?;
+
+    // A transliteration that does not match exactly should not rule out a unique Persian match
+    if ids.is_empty() && cross_ref.target_persian.is_some() && cross_ref.target_latin.is_some() {
+        let persian_only = CrossRef {
+            target_latin: None,
+            ..cross_ref.clone()
+        };
+        return find_targets(conn, from_id, &persian_only);
+    }
+
+    Ok(ids)
+}
+
+//
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    #[test]
+    fn extraction() {
+        let refs =
+            extract_cross_refs("Water; see آب رو *āb-rū*; for *ābād* q.v.; cf. ماء; see above");
+
+        assert_eq!(refs.len(), 3);
+
+        assert_eq!(refs[0].kind, RefKind::QuaVide);
+        assert_eq!(refs[0].target_persian, None);
+        assert_eq!(refs[0].target_latin.as_deref(), Some("ābād"));
+
+        assert_eq!(refs[1].kind, RefKind::See);
+        assert_eq!(refs[1].text, "see آب رو *āb-rū*");
+        assert_eq!(refs[1].target_persian.as_deref(), Some("آب رو"));
+        assert_eq!(refs[1].target_latin.as_deref(), Some("āb-rū"));
+
+        assert_eq!(refs[2].kind, RefKind::Compare);
+        assert_eq!(refs[2].target_persian.as_deref(), Some("ماء"));
+    }
+
+    #[test]
+    fn resolution() {
+        let conn = test_db(&[
+            (
+                3,
+                "<div><hw><pa>آب</pa> <i>āb</i></hw>, Water; see <i>ābād</i>; cf. آتش</div>",
+            ),
+            (
+                3,
+                "<div><hw><pa>آباد</pa> <i>ābād</i></hw>, Inhabited; see آب <i>āb</i></div>",
+            ),
+        ]);
+
+        let counts = resolve_cross_refs(&conn).unwrap();
+        assert_eq!(
+            counts,
+            ResolveCounts {
+                resolved: 2,
+                ambiguous: 0,
+                unresolved: 1,
+            }
+        );
+
+        let target: i64 = conn
+            .query_row(
+                "SELECT target_id FROM cross_refs WHERE entry_id = 2",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(target, 1);
+
+        let dangling = dangling_refs(&conn).unwrap();
+        assert_eq!(dangling.len(), 1);
+        assert_eq!(dangling[0].text, "cf. آتش");
+        assert_eq!(dangling[0].status, RefStatus::Unresolved);
+    }
+}
@@ -1,26 +1,13 @@
 #! /usr/bin/env bash
 
+# Adds the full-text tables, indexes and sync triggers to the remote D1 copy of the DB.
+# The SQL comes from the scraper itself (`fts --script`), so it matches the local schema;
+# writing it does not open (or migrate) the local DB.
+
 set -Eeuo pipefail
 
-npx wrangler d1 execute steingass-v2 --remote --command \
-	"CREATE VIRTUAL TABLE ft_all USING fts5(headword_full, definitions, content='entries', content_rowid='id')" &&
-	npx wrangler d1 execute steingass-v2 --remote --command \
-		"INSERT INTO ft_all (headword_full, definitions) SELECT headword_full, definitions FROM entries" &&
-	npx wrangler d1 execute steingass-v2 --remote --command \
-		"CREATE VIRTUAL TABLE ft_def USING fts5(definitions, content='entries', content_rowid='id')" &&
-	npx wrangler d1 execute steingass-v2 --remote --command \
-		"INSERT INTO ft_def (definitions) SELECT definitions FROM entries" &&
-	npx wrangler d1 execute steingass-v2 --remote --command \
-		"CREATE VIRTUAL TABLE ft_hw USING fts5(headword_full, content='entries', content_rowid='id')" &&
-	npx wrangler d1 execute steingass-v2 --remote --command \
-		"INSERT INTO ft_hw (headword_full) SELECT headword_full FROM entries" &&
-	npx wrangler d1 execute steingass-v2 --remote --command \
-		"CREATE VIRTUAL TABLE ft_per USING fts5(headword_persian, content='entries', content_rowid='id')" &&
-	npx wrangler d1 execute steingass-v2 --remote --command \
-		"INSERT INTO ft_per (headword_persian) SELECT headword_persian FROM entries" &&
-	npx wrangler d1 execute steingass-v2 --remote --command \
-		"CREATE INDEX idx_page ON entries(page)" &&
-	npx wrangler d1 execute steingass-v2 --remote --command \
-		"CREATE INDEX idx_per ON entries(headword_persian)" &&
-	npx wrangler d1 execute steingass-v2 --remote --command \
-		"CREATE INDEX idx_abjad ON entries(abjad)"
+script="$(mktemp --suffix .sql)"
+trap 'rm -f "$script"' EXIT
+
+cargo run --release -- fts --script "$script"
+npx wrangler d1 execute steingass-v2 --remote --file "$script"